@@ -0,0 +1,278 @@
+use std::fs::{ self, File, Metadata, OpenOptions };
+use std::io;
+use std::path::{ Path, PathBuf };
+use std::process;
+
+// links followed before giving up, like the kernel does
+const MAX_LINKS: usize = 40;
+
+// 安全保存: the text goes to a temp file next to `path`, is synced to disk and renamed over it,
+// so a crash or a full disk leaves the old file or the new one, never half of it
+pub fn save<T>(path: &Path, write: impl Fn(&mut File) -> io::Result<T>) -> io::Result<T> {
+    // a symlink stays a link, the file it points to gets the text
+    let path = resolve_symlinks(path);
+    let metadata = match fs::metadata(&path) {
+        Ok(metadata) if !metadata.is_file() => return Err(io::Error::other("not a regular file")),
+        Ok(metadata) => Some(metadata),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => None,
+        Err(err) => return Err(err),
+    };
+    // the rename would replace a file that can't be written, an in-place write would refuse
+    if let Some(metadata) = &metadata {
+        if metadata.permissions().readonly() {
+            return Err(io::Error::new(io::ErrorKind::PermissionDenied, "read-only file"));
+        }
+        OpenOptions::new().write(true).open(&path)?;
+    }
+    // a rename would split up hard links
+    if metadata.as_ref().is_some_and(|metadata| hard_links(metadata) > 1) {
+        return save_in_place(&path, write);
+    }
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    // a directory we can't write to still lets us overwrite a writable file
+    let (temp_path, mut temp) = match create_temp(&dir, &path) {
+        Ok(temp) => temp,
+        Err(err) if err.kind() == io::ErrorKind::PermissionDenied && metadata.is_some() => return save_in_place(&path, write),
+        Err(err) => return Err(err),
+    };
+    // the permissions go first, the text of a 0600 file must never be readable by others
+    let written = metadata
+        .as_ref()
+        .map_or(Ok(()), |metadata| temp.set_permissions(metadata.permissions()))
+        .and_then(|_| write(&mut temp))
+        .and_then(|written| {
+            temp.sync_all()?;
+            Ok(written)
+        });
+    let written = match written {
+        Ok(written) => written,
+        Err(err) => {
+            let _ = fs::remove_file(&temp_path);
+            return Err(err);
+        }
+    };
+    // only root may give a file away, the others keep the owner by writing in place
+    if let Some(metadata) = &metadata {
+        if keep_owner(&temp, metadata).is_err() {
+            let _ = fs::remove_file(&temp_path);
+            return save_in_place(&path, write);
+        }
+    }
+    drop(temp);
+    if let Err(err) = fs::rename(&temp_path, &path) {
+        let _ = fs::remove_file(&temp_path);
+        return Err(err);
+    }
+    // the rename itself is only durable once the directory is synced
+    if let Ok(dir) = File::open(&dir) {
+        let _ = dir.sync_all();
+    }
+    Ok(written)
+}
+
+fn resolve_symlinks(path: &Path) -> PathBuf {
+    let mut path = path.to_path_buf();
+    for _ in 0..MAX_LINKS {
+        match fs::read_link(&path) {
+            // a relative target is relative to the link's directory
+            Ok(target) => path = path.parent().unwrap_or(Path::new("")).join(target),
+            Err(_) => break,
+        }
+    }
+    path
+}
+
+// `.name.1234.tmp`, hidden and next to the file so the rename stays on one file system
+fn create_temp(dir: &Path, path: &Path) -> io::Result<(PathBuf, File)> {
+    let name = path.file_name().map(|name| name.to_string_lossy()).unwrap_or_default();
+    let mut attempt = 0;
+    loop {
+        let temp_path = dir.join(format!(".{}.{}.{}.tmp", name, process::id(), attempt));
+        match OpenOptions::new().write(true).create_new(true).open(&temp_path) {
+            Ok(file) => return Ok((temp_path, file)),
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists && attempt < 100 => attempt += 1,
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+// the fallback: the file keeps its inode, owner and links, but a crash may leave it half written
+fn save_in_place<T>(path: &Path, write: impl Fn(&mut File) -> io::Result<T>) -> io::Result<T> {
+    let mut file = OpenOptions::new().write(true).create(true).truncate(true).open(path)?;
+    let written = write(&mut file)?;
+    file.sync_all()?;
+    Ok(written)
+}
+
+#[cfg(unix)]
+fn keep_owner(file: &File, metadata: &Metadata) -> io::Result<()> {
+    use std::os::unix::fs::{ fchown, MetadataExt };
+    let current = file.metadata()?;
+    if (current.uid(), current.gid()) == (metadata.uid(), metadata.gid()) {
+        return Ok(());
+    }
+    fchown(file, Some(metadata.uid()), Some(metadata.gid()))
+}
+
+#[cfg(not(unix))]
+fn keep_owner(_file: &File, _metadata: &Metadata) -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(unix)]
+fn hard_links(metadata: &Metadata) -> u64 {
+    std::os::unix::fs::MetadataExt::nlink(metadata)
+}
+
+#[cfg(not(unix))]
+fn hard_links(_metadata: &Metadata) -> u64 {
+    1
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use std::os::unix::fs::{ self as unix_fs, MetadataExt, PermissionsExt };
+
+    // an empty directory of its own for each test
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("pound-save-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn save_text(path: &Path, text: &str) -> io::Result<usize> {
+        save(path, |file| file.write_all(text.as_bytes()).map(|_| text.len()))
+    }
+
+    fn temp_files(dir: &Path) -> Vec<PathBuf> {
+        fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "tmp"))
+            .collect()
+    }
+
+    fn set_mode(path: &Path, mode: u32) {
+        fs::set_permissions(path, fs::Permissions::from_mode(mode)).unwrap();
+    }
+
+    #[test]
+    fn replaces_the_file() {
+        let dir = temp_dir("replace");
+        let path = dir.join("a.txt");
+        assert_eq!(save_text(&path, "new file").unwrap(), 8);
+        fs::write(&path, "old").unwrap();
+        let inode = fs::metadata(&path).unwrap().ino();
+        save_text(&path, "new").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new");
+        // a new file took its place
+        assert_ne!(fs::metadata(&path).unwrap().ino(), inode);
+        assert!(temp_files(&dir).is_empty());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn a_symlink_stays_a_link() {
+        let dir = temp_dir("symlink");
+        fs::create_dir(dir.join("real")).unwrap();
+        fs::write(dir.join("real/a.txt"), "old").unwrap();
+        unix_fs::symlink("real/a.txt", dir.join("link")).unwrap();
+        unix_fs::symlink("link", dir.join("link2")).unwrap();
+        save_text(&dir.join("link2"), "new").unwrap();
+        assert!(fs::symlink_metadata(dir.join("link")).unwrap().file_type().is_symlink());
+        assert!(fs::symlink_metadata(dir.join("link2")).unwrap().file_type().is_symlink());
+        assert_eq!(fs::read_to_string(dir.join("real/a.txt")).unwrap(), "new");
+        assert!(temp_files(&dir.join("real")).is_empty());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn keeps_permissions_and_owner() {
+        let dir = temp_dir("permissions");
+        let path = dir.join("a.txt");
+        fs::write(&path, "old").unwrap();
+        set_mode(&path, 0o640);
+        let before = fs::metadata(&path).unwrap();
+        // root can give the file away, the temp file has to get the same owner
+        let owner = if before.uid() == 0 { (65534, 65534) } else { (before.uid(), before.gid()) };
+        unix_fs::chown(&path, Some(owner.0), Some(owner.1)).unwrap();
+        save_text(&path, "new").unwrap();
+        let after = fs::metadata(&path).unwrap();
+        assert_eq!(after.mode() & 0o7777, 0o640);
+        assert_eq!((after.uid(), after.gid()), owner);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn hard_links_are_written_in_place() {
+        let dir = temp_dir("hard-link");
+        let path = dir.join("a.txt");
+        fs::write(&path, "old text").unwrap();
+        fs::hard_link(&path, dir.join("b.txt")).unwrap();
+        let inode = fs::metadata(&path).unwrap().ino();
+        save_text(&path, "new").unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().ino(), inode);
+        assert_eq!(fs::read_to_string(dir.join("b.txt")).unwrap(), "new");
+        assert!(temp_files(&dir).is_empty());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn an_unwritable_directory_is_written_in_place() {
+        let dir = temp_dir("read-only-dir");
+        let path = dir.join("a.txt");
+        fs::write(&path, "old").unwrap();
+        let inode = fs::metadata(&path).unwrap().ino();
+        set_mode(&dir, 0o555);
+        // root may still create the temp file, then the rename is fine too
+        let root = OpenOptions::new().write(true).create_new(true).open(dir.join("probe")).is_ok();
+        let result = save_text(&path, "new");
+        set_mode(&dir, 0o755);
+        result.unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new");
+        if !root {
+            assert_eq!(fs::metadata(&path).unwrap().ino(), inode);
+        }
+        assert!(temp_files(&dir).is_empty());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn a_read_only_file_is_refused() {
+        let dir = temp_dir("read-only");
+        let path = dir.join("a.txt");
+        fs::write(&path, "old").unwrap();
+        set_mode(&path, 0o444);
+        let err = save_text(&path, "new").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "old");
+        assert!(temp_files(&dir).is_empty());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn a_failed_write_leaves_the_file_and_no_temp_file() {
+        let dir = temp_dir("failed");
+        let path = dir.join("a.txt");
+        fs::write(&path, "old").unwrap();
+        let result = save(&path, |_| -> io::Result<()> { Err(io::Error::other("disk full")) });
+        assert_eq!(result.unwrap_err().to_string(), "disk full");
+        assert_eq!(fs::read_to_string(&path).unwrap(), "old");
+        assert!(temp_files(&dir).is_empty());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn not_a_regular_file() {
+        let dir = temp_dir("directory");
+        assert!(save_text(&dir, "text").is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,22 @@
+use crate::{ cursor_xy::CursorController, editor_row::EditorRows, history::History, search_direction::SearchIndex };
+
+// 一个打开的文件, 切换时和 `Output` 里的字段交换
+pub struct Buffer {
+    pub editor_rows: EditorRows,
+    pub cursor_controller: CursorController,
+    pub dirty: u64,
+    pub history: History,
+    pub search_index: SearchIndex,
+}
+
+impl Buffer {
+    pub fn new(editor_rows: EditorRows, win_size: (usize, usize)) -> Self {
+        Self {
+            editor_rows,
+            cursor_controller: CursorController::new(win_size),
+            dirty: 0,
+            history: History::new(),
+            search_index: SearchIndex::new(),
+        }
+    }
+}
@@ -0,0 +1,49 @@
+const KILL_RING_SIZE: usize = 60;
+
+// 剪贴板
+pub struct Clipboard {
+    pub text: Option<String>,
+    pub linewise: bool, // whole rows, e.g. vim `yy` / `dd`
+    ring: Vec<String>, // 剪切环: the older texts too, emacs `M-y` goes back through them
+    yank_idx: usize, // the entry of `ring` yanked last
+}
+
+impl Clipboard {
+    pub fn new() -> Self {
+        Self { text: None, linewise: false, ring: Vec::new(), yank_idx: 0 }
+    }
+    pub fn set(&mut self, text: String, linewise: bool) {
+        if self.ring.len() == KILL_RING_SIZE {
+            self.ring.remove(0);
+        }
+        self.ring.push(text.clone());
+        self.yank_idx = self.ring.len() - 1;
+        self.text = Some(text);
+        self.linewise = linewise;
+    }
+    // consecutive kills make one entry
+    pub fn append(&mut self, text: &str) {
+        match self.ring.last_mut() {
+            Some(last) => {
+                last.push_str(text);
+                self.text = Some(last.clone());
+                self.yank_idx = self.ring.len() - 1;
+                self.linewise = false;
+            }
+            None => self.set(text.to_string(), false),
+        }
+    }
+    // emacs `C-y`, the newest entry
+    pub fn yank(&mut self) -> Option<&str> {
+        self.yank_idx = self.ring.len().saturating_sub(1);
+        self.text.as_deref()
+    }
+    // the entry before the one yanked last, wrapping around
+    pub fn rotate(&mut self) -> Option<&str> {
+        if self.ring.is_empty() {
+            return None;
+        }
+        self.yank_idx = (self.yank_idx + self.ring.len() - 1) % self.ring.len();
+        Some(&self.ring[self.yank_idx])
+    }
+}
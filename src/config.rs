@@ -0,0 +1,126 @@
+use std::{ collections::BTreeMap, env, fs, path::PathBuf };
+use serde::Deserialize;
+use crate::cursor_xy::Wrap;
+use crate::editor_row::TAB_STOP;
+use crate::gutter::LineNumbers;
+use crate::keymap::Keymap;
+
+// 配置文件: `~/.config/pound/config.toml`, then `.pound.toml` of the project, its keys win
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub tab_width: usize,
+    pub soft_tabs: bool, // Tab inserts spaces
+    pub wrap: Wrap,
+    pub line_numbers: LineNumbers,
+    pub theme: String, // `themes/<name>.toml` here or a built-in one, see `Theme::load`
+    pub message_timeout: u64, // seconds
+    pub quit_confirmations: u8, // extra Ctrl-Q presses to quit with unsaved changes
+    pub keymap: String, // "default", "emacs" or "vim", under `keys`
+    pub keys: BTreeMap<String, String>, // `[keys]`, e.g. "C-x C-s" = "save", see `Keymap::new`
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            tab_width: TAB_STOP,
+            soft_tabs: false,
+            wrap: Wrap::Off,
+            line_numbers: LineNumbers::Off,
+            theme: "default".to_string(),
+            message_timeout: 5,
+            quit_confirmations: 2,
+            keymap: "default".to_string(),
+            keys: BTreeMap::new(),
+        }
+    }
+}
+
+impl Config {
+    pub const PROJECT_FILE: &'static str = ".pound.toml";
+
+    // `~/.config/pound`, also home of the `syntaxes` directory
+    pub fn dir() -> Option<PathBuf> {
+        let config_dir = match env::var_os("XDG_CONFIG_HOME") {
+            Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+            _ => dirs::home_dir()?.join(".config"),
+        };
+        Some(config_dir.join("pound"))
+    }
+
+    fn user_file() -> Option<PathBuf> {
+        Some(Config::dir()?.join("config.toml"))
+    }
+
+    // the nearest `.pound.toml` from the working directory up
+    fn project_file() -> Option<PathBuf> {
+        let cwd = env::current_dir().ok()?;
+        cwd.ancestors()
+            .map(|dir| dir.join(Config::PROJECT_FILE))
+            .find(|path| path.is_file())
+    }
+
+    // settings of every file that exists, plus the problems found in them.
+    // a file that doesn't parse is skipped, a bad value falls back to the default
+    pub fn load() -> (Self, Vec<String>) {
+        let mut table = toml::Table::new();
+        let mut errors = Vec::new();
+        for path in [Config::user_file(), Config::project_file()].into_iter().flatten() {
+            let text = match fs::read_to_string(&path) {
+                Ok(text) => text,
+                Err(_) => continue,
+            };
+            match Config::parse(&text) {
+                Ok(file) => Config::merge(&mut table, file),
+                Err(err) => errors.push(format!("{}: {}", path.display(), err)),
+            }
+        }
+        let mut config = toml::Value::Table(table).try_into().unwrap_or_default();
+        errors.extend(Config::validate(&mut config));
+        (config, errors)
+    }
+
+    // tables like `[keys]` are merged key by key
+    fn merge(table: &mut toml::Table, file: toml::Table) {
+        for (key, value) in file {
+            match (table.get_mut(&key), value) {
+                (Some(toml::Value::Table(table)), toml::Value::Table(file)) => table.extend(file),
+                (_, value) => {
+                    table.insert(key, value);
+                }
+            }
+        }
+    }
+
+    fn parse(text: &str) -> Result<toml::Table, String> {
+        let error = |err| Config::describe_error(text, err);
+        // unknown keys and wrong types
+        toml::from_str::<Config>(text).map_err(error)?;
+        text.parse::<toml::Table>().map_err(error)
+    }
+
+    // `line 3: invalid type ...`
+    pub fn describe_error(text: &str, err: toml::de::Error) -> String {
+        match err.span() {
+            Some(span) => format!("line {}: {}", text[..span.start].matches('\n').count() + 1, err.message()),
+            None => err.message().to_string(),
+        }
+    }
+
+    fn validate(config: &mut Config) -> Vec<String> {
+        let mut errors = Vec::new();
+        if !(1..=16).contains(&config.tab_width) {
+            errors.push(format!("tab_width must be between 1 and 16, not {}", config.tab_width));
+            config.tab_width = TAB_STOP;
+        }
+        if !Keymap::PROFILES.contains(&config.keymap.as_str()) {
+            errors.push(format!("unknown keymap `{}`, expected one of {}", config.keymap, Keymap::PROFILES.join(", ")));
+            config.keymap = "default".to_string();
+        }
+        if config.message_timeout == 0 {
+            errors.push("message_timeout must be at least 1 second".to_string());
+            config.message_timeout = 5;
+        }
+        errors
+    }
+}
@@ -1,6 +1,35 @@
 use std::cmp;
 use crossterm::event::KeyCode;
-use crate::editor_row::{ EditorRows, Row, TAB_STOP };
+use serde::Deserialize;
+use crate::editor_row::EditorRows;
+use crate::history::Position;
+
+// 自动换行
+#[derive(Copy, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Wrap {
+    Off,
+    Char,
+    Word, // at word boundaries when possible
+}
+
+impl Wrap {
+    pub fn next(self) -> Self {
+        match self {
+            Wrap::Off => Wrap::Char,
+            Wrap::Char => Wrap::Word,
+            Wrap::Word => Wrap::Off,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Wrap::Off => "off",
+            Wrap::Char => "on",
+            Wrap::Word => "on, at word boundaries",
+        }
+    }
+}
 
 // 处理游标
 #[derive(Copy,Clone)]
@@ -9,9 +38,16 @@ pub struct CursorController {
     pub cursor_y: usize,
     pub screen_columns: usize,
     pub screen_rows: usize,
+    pub gutter_width: usize, // line numbers, part of `screen_columns`
     pub row_offset: usize,
     pub column_offset: usize,
     pub render_x: usize, // tab position the cursor on the screen
+    pub anchor: Option<Position>, // the other end of the selection
+    pub mark_active: bool, // the anchor was set with emacs `C-Space`, moving keeps the selection
+    pub wrap: Wrap,
+    pub wrap_offset: usize, // screen lines of the `row_offset` row scrolled out when wrapping
+    pub screen_y: Option<usize>, // the cursor's line in the window, `None` once scrolled out of it
+    pub free_scroll: bool, // the mouse wheel moved the view, leave it where it is
 }
 
 impl CursorController {
@@ -22,14 +58,41 @@ impl CursorController {
             cursor_y: 0,
             screen_columns: cols,
             screen_rows: rows,
+            gutter_width: 0,
             row_offset: 0,
             column_offset: 0,
             render_x: 0,
+            anchor: None,
+            mark_active: false,
+            wrap: Wrap::Off,
+            wrap_offset: 0,
+            screen_y: Some(0),
+            free_scroll: false,
+        }
+    }
+    // columns left for the text
+    pub fn text_columns(&self) -> usize {
+        self.screen_columns.saturating_sub(self.gutter_width)
+    }
+    // (start, end) of the selection, in document order
+    pub fn selection(&self) -> Option<(Position, Position)> {
+        let anchor = self.anchor?;
+        let cursor = (self.cursor_x, self.cursor_y);
+        if (anchor.1, anchor.0) <= (cursor.1, cursor.0) {
+            Some((anchor, cursor))
+        } else {
+            Some((cursor, anchor))
         }
     }
     pub fn move_cursor(&mut self, direction: KeyCode, editor_rows: &EditorRows) {
         let number_of_rows = editor_rows.number_of_rows();
+        let row_len = |y: usize| {
+            if y < number_of_rows { editor_rows.get_editor_row(y).len() } else { 0 }
+        };
         match direction {
+            KeyCode::Up | KeyCode::Down if self.wrap != Wrap::Off => {
+                self.move_visual(direction == KeyCode::Down, editor_rows);
+            }
             KeyCode::Up => {
                 self.cursor_y = self.cursor_y.saturating_sub(1);
             }
@@ -39,7 +102,7 @@ impl CursorController {
                 } else {
                     if self.cursor_y > 0 {
                         self.cursor_y -= 1;
-                        self.cursor_x = editor_rows.get_render_row(self.cursor_y).len();
+                        self.cursor_x = row_len(self.cursor_y);
                     }
                 }
             }
@@ -61,7 +124,7 @@ impl CursorController {
                 //     }
                 // }
                 if self.cursor_y < number_of_rows {
-                    match self.render_x.cmp(&editor_rows.get_render_row(self.cursor_y).len()) {
+                    match self.cursor_x.cmp(&row_len(self.cursor_y)) {
                         cmp::Ordering::Less => {
                             self.cursor_x += 1;
                         }
@@ -74,41 +137,95 @@ impl CursorController {
                 }
             }
             KeyCode::End => {
-                self.cursor_x = editor_rows.get_render_row(self.cursor_y).len();
+                self.cursor_x = row_len(self.cursor_y);
             }
             KeyCode::Home => {
                 self.cursor_x = 0;
             }
             _ => unreachable!(),
         }
-        let current_row_len = if self.cursor_y < number_of_rows {
-            editor_rows.get_render_row(self.cursor_y).len()
-        } else {
-            0
-        };
         // 游标位置
-        self.cursor_x = cmp::min(self.cursor_x, current_row_len);
+        self.cursor_x = cmp::min(self.cursor_x, row_len(self.cursor_y));
     }
 
-    fn get_render_x(&self, row: &Row) -> usize {
-        row.row_content[..self.cursor_x].chars().fold(0, |render_x, c| {
-            if c == '\t' {
-                render_x + (TAB_STOP - 1) - (render_x % TAB_STOP) + 1
-            } else {
-                render_x + 1
+    // the start of each screen line of row `y`, see `Row::wrap`
+    pub fn segments(&self, editor_rows: &EditorRows, y: usize) -> Vec<usize> {
+        if self.wrap == Wrap::Off || y >= editor_rows.number_of_rows() {
+            return vec![0];
+        }
+        editor_rows.get_editor_row(y).wrap(self.text_columns(), self.wrap == Wrap::Word)
+    }
+
+    // the screen line of `segments` holding the render byte `idx`
+    fn segment_of(segments: &[usize], idx: usize) -> usize {
+        segments.iter().rposition(|&start| start <= idx).unwrap_or(0)
+    }
+
+    // (screen line of the cursor inside its row, column inside that line)
+    fn visual_position(&self, editor_rows: &EditorRows) -> (usize, usize) {
+        if self.cursor_y >= editor_rows.number_of_rows() {
+            return (0, 0);
+        }
+        let row = editor_rows.get_editor_row(self.cursor_y);
+        let segments = self.segments(editor_rows, self.cursor_y);
+        let idx = row.render_index(self.cursor_x);
+        let line = CursorController::segment_of(&segments, idx);
+        (line, row.render_column(idx) - row.render_column(segments[line]))
+    }
+
+    // up / down by screen line while wrapping
+    fn move_visual(&mut self, down: bool, editor_rows: &EditorRows) {
+        let number_of_rows = editor_rows.number_of_rows();
+        let (line, column) = self.visual_position(editor_rows);
+        let lines = self.segments(editor_rows, self.cursor_y).len();
+        let (y, line) = match down {
+            true if line + 1 < lines => (self.cursor_y, line + 1),
+            true if self.cursor_y < number_of_rows => (self.cursor_y + 1, 0),
+            false if line > 0 => (self.cursor_y, line - 1),
+            false if self.cursor_y > 0 => {
+                let y = self.cursor_y - 1;
+                (y, self.segments(editor_rows, y).len() - 1)
+            }
+            _ => {
+                return;
             }
-        })
+        };
+        self.cursor_y = y;
+        if y >= number_of_rows {
+            self.cursor_x = 0;
+            return;
+        }
+        let row = editor_rows.get_editor_row(y);
+        let segments = self.segments(editor_rows, y);
+        let mut x = row.get_row_content_x(row.render_column(segments[line]) + column);
+        // stay on this screen line
+        if let Some(&end) = segments.get(line + 1) {
+            while x > 0 && row.render_index(x) >= end {
+                x -= 1;
+            }
+        }
+        self.cursor_x = x;
     }
+
     //窗口垂直、水平滚动
     pub fn scroll(&mut self, editor_rows: &EditorRows) {
         self.render_x = 0;
+        if self.wrap != Wrap::Off {
+            self.scroll_wrapped(editor_rows);
+            return;
+        }
         if self.cursor_y < editor_rows.number_of_rows() {
-            self.render_x = self.get_render_x(editor_rows.get_editor_row(self.cursor_y));
+            self.render_x = editor_rows.get_editor_row(self.cursor_y).get_render_x(self.cursor_x);
         }
+        self.wrap_offset = 0;
 
-        self.row_offset = cmp::min(self.row_offset, self.cursor_y);
-        if self.cursor_y >= self.row_offset + self.screen_rows {
-            self.row_offset = self.cursor_y - self.screen_rows + 1;
+        // a window may have no room left after a resize
+        let screen_rows = cmp::max(self.screen_rows, 1);
+        if !self.free_scroll {
+            self.row_offset = cmp::min(self.row_offset, self.cursor_y);
+            if self.cursor_y >= self.row_offset + screen_rows {
+                self.row_offset = self.cursor_y + 1 - screen_rows;
+            }
         }
 
         // self.column_offset = cmp::min(self.column_offset, self.cursor_x);
@@ -116,8 +233,77 @@ impl CursorController {
         //     self.column_offset = self.cursor_x - self.screen_columns + 1;
         // }
         self.column_offset = cmp::min(self.column_offset, self.render_x);
-        if self.render_x >= self.column_offset + self.screen_columns {
-            self.column_offset = self.render_x - self.screen_columns + 1;
+        let text_columns = cmp::max(self.text_columns(), 1);
+        if self.render_x >= self.column_offset + text_columns {
+            self.column_offset = self.render_x + 1 - text_columns;
+        }
+        self.screen_y = self.cursor_y
+            .checked_sub(self.row_offset)
+            .filter(|&screen_y| screen_y < screen_rows);
+    }
+
+    // like `scroll`, counting screen lines instead of rows
+    fn scroll_wrapped(&mut self, editor_rows: &EditorRows) {
+        self.column_offset = 0;
+        let (line, column) = self.visual_position(editor_rows);
+        self.render_x = column;
+        // every row takes at least one line
+        if !self.free_scroll && self.cursor_y >= self.row_offset + self.screen_rows {
+            self.row_offset = self.cursor_y + 1 - cmp::max(self.screen_rows, 1);
+            self.wrap_offset = 0;
+        }
+        self.wrap_offset = cmp::min(self.wrap_offset, self.segments(editor_rows, self.row_offset).len() - 1);
+        if self.free_scroll {
+            self.screen_y = None;
+            if (self.cursor_y, line) >= (self.row_offset, self.wrap_offset) && self.cursor_y < self.row_offset + self.screen_rows {
+                let screen_y = (self.row_offset..self.cursor_y)
+                    .map(|y| self.segments(editor_rows, y).len())
+                    .sum::<usize>() + line - self.wrap_offset;
+                self.screen_y = Some(screen_y).filter(|&screen_y| screen_y < self.screen_rows);
+            }
+            return;
+        }
+        if (self.cursor_y, line) < (self.row_offset, self.wrap_offset) {
+            self.row_offset = self.cursor_y;
+            self.wrap_offset = line;
+        }
+        let mut screen_y = (self.row_offset..self.cursor_y)
+            .map(|y| self.segments(editor_rows, y).len())
+            .sum::<usize>() + line - self.wrap_offset;
+        while screen_y >= cmp::max(self.screen_rows, 1) {
+            if self.wrap_offset + 1 < self.segments(editor_rows, self.row_offset).len() {
+                self.wrap_offset += 1;
+            } else {
+                self.row_offset += 1;
+                self.wrap_offset = 0;
+            }
+            screen_y -= 1;
+        }
+        self.screen_y = Some(screen_y);
+    }
+
+    // moves the view by screen lines, the cursor stays where it is
+    pub fn scroll_by(&mut self, lines: isize, editor_rows: &EditorRows) {
+        self.free_scroll = true;
+        let last_row = editor_rows.number_of_rows().saturating_sub(1);
+        for _ in 0..lines.unsigned_abs() {
+            if lines > 0 {
+                if self.wrap_offset + 1 < self.segments(editor_rows, self.row_offset).len() {
+                    self.wrap_offset += 1;
+                } else if self.row_offset < last_row {
+                    self.row_offset += 1;
+                    self.wrap_offset = 0;
+                } else {
+                    break;
+                }
+            } else if self.wrap_offset > 0 {
+                self.wrap_offset -= 1;
+            } else if self.row_offset > 0 {
+                self.row_offset -= 1;
+                self.wrap_offset = self.segments(editor_rows, self.row_offset).len() - 1;
+            } else {
+                break;
+            }
         }
     }
 }
\ No newline at end of file
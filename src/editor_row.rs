@@ -1,106 +1,261 @@
-use std::{ fs, env, path::PathBuf, io::{ self, Write } };
+use std::{ cmp, env, fs, iter, ops::Range, path::{ Path, PathBuf }, io };
 
 use crossterm::style::Color;
+use ropey::Rope;
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
 
-use crate::{syntax_struct::SyntaxHighlight, Output};
+use crate::{ syntax_struct::{ LineState, SyntaxHighlight }, history::Position, atomic_save, file_format::FileFormat };
 
-pub const TAB_STOP: usize = 8;
+pub const TAB_STOP: usize = 8; // default, see `Config::tab_width`
+// a row further down than this from the highlighted ones is drawn from the old states
+const SYNC_ROWS: usize = 1000;
+// 文本保存在 rope 里, 行的 render / highlight 在需要时才计算
 pub struct EditorRows {
-    pub row_contents: Vec<Row>,
+    text: Rope, // rows joined by '\n', without a trailing one
+    empty: bool, // no rows at all, `text` alone can't tell it from one empty row
     pub filename: Option<PathBuf>,
+    pub syntax_highlight: Option<Box<dyn SyntaxHighlight>>,
+    pub tab_stop: usize,
+    pub format: FileFormat, // line endings, final newline and BOM of the file
+    saved_format: FileFormat, // `format` on disk, changing it is an unsaved change too
+    // what row i leaves open. the first `syntax_valid` are up to date, the rest are from before
+    // the last edits and still line up with their rows
+    syntax_states: Vec<LineState>,
+    syntax_valid: usize,
+    syntax_dirty: usize, // rows before it were edited, the old states after it may still be right
+    syntax_guessed: usize, // rows before it were drawn from old states, redraw once they are valid
 }
 impl EditorRows {
-    pub fn new(syntax_highlight: &mut Option<Box<dyn SyntaxHighlight>>) -> Self {
-        let mut arg = env::args();
-        match arg.nth(1) {
-            Some(file_path) => Self::from_file(file_path.into(), syntax_highlight),
-            None => Self { row_contents: Vec::new(), filename: None },
+    pub fn new() -> Self {
+        Self::from_text(String::new(), None)
+    }
+    fn from_text(text: String, filename: Option<PathBuf>) -> Self {
+        Self {
+            empty: text.is_empty(),
+            text: Rope::from_str(&text),
+            filename,
+            syntax_highlight: None,
+            tab_stop: TAB_STOP,
+            format: FileFormat::default(),
+            saved_format: FileFormat::default(),
+            syntax_states: Vec::new(),
+            syntax_valid: 0,
+            syntax_dirty: 0,
+            syntax_guessed: 0,
         }
     }
     pub fn number_of_rows(&self) -> usize {
-        self.row_contents.len()
+        if self.empty { 0 } else { self.text.len_lines() }
     }
     // pub fn get_row_str(&self, at: usize) -> &str {
     //     &self.row_contents[at].render
     // }
-    pub fn get_render_row(&self, at: usize) -> &String {
-        &self.row_contents[at].render
-    }
-    pub fn get_editor_row(&self, at: usize) -> &Row {
-        &self.row_contents[at]
-    }
-    fn from_file(file_path: PathBuf, syntax_highlight: &mut Option<Box<dyn SyntaxHighlight>>) -> Self {
-        let file_content = fs::read_to_string(&file_path).expect("Unable to read file.");
-        let mut row_contents = Vec::new();
-
-        file_path.extension()
-            .and_then(|ext| ext.to_str())
-            .map(|ext| Output::select_syntax(ext)
-            .map(|syntax| syntax_highlight.insert(syntax)));
-
-        file_content
-            .lines()
-            .enumerate()
-            .for_each(|(i, line)| {
-                let mut row = Row::new(line.into(), String::new());
-                EditorRows::render_row(&mut row);
-                row_contents.push(row);
-                if let Some(it) = syntax_highlight {
-                    it.update_syntax(i, &mut row_contents);
-                }
-            });
-        Self { row_contents, filename: Some(file_path) }
+    pub fn get_editor_row(&self, at: usize) -> Row {
+        let line = self.text.line(at);
+        let mut row_content = line.to_string();
+        if row_content.ends_with('\n') {
+            row_content.pop();
+        }
+        let mut row = Row::new(row_content, String::new());
+        row.tab_stop = self.tab_stop;
+        EditorRows::render_row(&mut row);
+        row
+    }
+    // like `get_editor_row`, with `highlight` filled in
+    pub fn get_highlighted_row(&mut self, at: usize) -> Row {
+        let mut row = self.get_editor_row(at);
+        if self.syntax_highlight.is_none() {
+            return row;
+        }
+        // rows above only need their end state, far down the old one stands in for now
+        if at > self.syntax_valid && at - self.syntax_valid <= SYNC_ROWS {
+            self.highlight_rows(at - self.syntax_valid);
+        }
+        if at > self.syntax_valid {
+            self.syntax_guessed = cmp::max(self.syntax_guessed, at + 1);
+        }
+        let state = if at > 0 { self.syntax_states.get(at - 1).copied().unwrap_or_default() } else { LineState::Normal };
+        if let Some(syntax) = self.syntax_highlight.as_ref() {
+            row.state = syntax.highlight_row(&row.render, state, &mut row.highlight);
+        }
+        if at == self.syntax_valid {
+            self.store_state(at, row.state);
+        }
+        row
+    }
+    // the end states of up to `rows` more rows, returns whether some are left
+    pub fn highlight_rows(&mut self, rows: usize) -> bool {
+        let mut highlight = Vec::new();
+        for _ in 0..rows {
+            let at = self.syntax_valid;
+            let syntax = match self.syntax_highlight.as_ref() {
+                Some(syntax) if at < self.number_of_rows() => syntax,
+                _ => return false,
+            };
+            let before = if at > 0 { self.syntax_states[at - 1] } else { LineState::Normal };
+            let state = syntax.highlight_row(&self.get_editor_row(at).render, before, &mut highlight);
+            self.store_state(at, state);
+        }
+        self.highlight_pending()
+    }
+    pub fn format_saved(&self) -> bool {
+        self.format == self.saved_format
+    }
+    pub fn highlight_pending(&self) -> bool {
+        self.syntax_highlight.is_some() && self.syntax_valid < self.number_of_rows()
+    }
+    // rows were drawn from old states that are up to date now
+    pub fn needs_redraw(&mut self) -> bool {
+        let redraw = self.syntax_guessed > 0 && self.syntax_valid >= cmp::min(self.syntax_guessed, self.number_of_rows());
+        if redraw {
+            self.syntax_guessed = 0;
+        }
+        redraw
+    }
+    // the state of row `syntax_valid`. an old state that comes out the same again past the
+    // edited rows means the ones after it are right too. a state that changed leaves the row
+    // after it unchecked, so it counts as edited
+    fn store_state(&mut self, at: usize, state: LineState) {
+        if at < self.syntax_states.len() {
+            let changed = self.syntax_states[at] != state;
+            if changed {
+                self.syntax_dirty = cmp::max(self.syntax_dirty, at + 1);
+            }
+            let converged = at >= self.syntax_dirty && !changed;
+            self.syntax_states[at] = state;
+            self.syntax_valid = if converged { self.syntax_states.len() } else { at + 1 };
+        } else {
+            self.syntax_states.push(state);
+            self.syntax_valid = at + 1;
+        }
+        if self.syntax_valid == self.syntax_states.len() {
+            self.syntax_dirty = 0;
+        }
+    }
+    pub fn set_syntax(&mut self, syntax_highlight: Option<Box<dyn SyntaxHighlight>>) {
+        self.syntax_highlight = syntax_highlight;
+        self.syntax_states.clear();
+        self.syntax_valid = 0;
+        self.syntax_dirty = 0;
+    }
+    // row `at` changed, the `removed` rows after it were joined to it and `added` new ones follow it.
+    // the old states of the rows below move along with them
+    fn invalidate_syntax(&mut self, at: usize, removed: usize, added: usize) {
+        if at < self.syntax_states.len() {
+            let end = cmp::min(at + 1 + removed, self.syntax_states.len());
+            self.syntax_states.splice(at + 1..end, iter::repeat_n(LineState::Normal, added));
+        }
+        if self.syntax_dirty > at {
+            self.syntax_dirty = cmp::max(self.syntax_dirty.saturating_sub(removed), at + 1) + added;
+        }
+        self.syntax_dirty = cmp::max(self.syntax_dirty, at + added + 1);
+        self.syntax_valid = cmp::min(self.syntax_valid, at);
+    }
+    // a missing file opens as an empty buffer, created on save. `Syntaxes::for_rows` picks the syntax
+    pub fn from_file(file_path: PathBuf) -> io::Result<Self> {
+        let bytes = match fs::read(&file_path) {
+            Ok(bytes) => bytes,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(err) => return Err(err),
+        };
+        // a file of just a newline still has one empty row
+        let empty = bytes.is_empty();
+        let (format, file_content) = FileFormat::detect(bytes)?;
+        let mut editor_rows = Self::from_text(file_content, Some(file_path));
+        editor_rows.format = format;
+        editor_rows.saved_format = format;
+        editor_rows.empty = empty;
+        Ok(editor_rows)
+    }
+    // `./a.rs`, `a.rs` and a link to it are the same file, one that doesn't exist yet is
+    // compared by its directory
+    pub fn is_file(&self, path: &Path) -> bool {
+        self.filename.as_deref().is_some_and(|filename| canonical(filename) == canonical(path))
     }
     pub fn render_row(row: &mut Row) {
-        let mut index = 0;
-        let capacity = row.row_content
-            .chars()
-            .fold(0, |acc, next| acc + (if next == '\t' { 8 } else { 1 }));
-        row.render = String::with_capacity(capacity);
-        row.row_content.chars().for_each(|c| {
-            index += 1;
-            if c == '\t' {
+        let mut column = 0;
+        row.render = String::with_capacity(row.row_content.len());
+        row.row_content.graphemes(true).for_each(|g| {
+            if g == "\t" {
                 // 用空格代替tab
-                row.render.push(' ');
-                while index % TAB_STOP != 0 {
-                    row.render.push(' ');
-                    index += 1;
-                }
+                let spaces = row.tab_stop - (column % row.tab_stop);
+                (0..spaces).for_each(|_| row.render.push(' '));
+                column += spaces;
             } else {
-                row.render.push(c);
+                row.render.push_str(g);
+                column += grapheme_width(g);
             }
         });
     }
     pub fn insert_row(&mut self, at: usize, contents: String) {
-        let mut new_row = Row::new(contents, String::new());
-        EditorRows::render_row(&mut new_row);
-        self.row_contents.insert(at, new_row);
-    }
-    pub fn get_editor_row_mut(&mut self, at: usize) -> &mut Row {
-        &mut self.row_contents[at]
+        if self.empty {
+            self.text.insert(0, &contents);
+            self.empty = false;
+        } else if at == self.number_of_rows() {
+            self.text.insert(self.text.len_chars(), &format!("\n{}", contents));
+        } else {
+            self.text.insert(self.text.line_to_char(at), &format!("{}\n", contents));
+        }
+        self.invalidate_syntax(at, 0, 1);
     }
-    pub fn save(&self) -> io::Result<usize> {
+    pub fn save(&mut self) -> io::Result<usize> {
         match &self.filename {
             Some(name) => {
-                let mut file = fs::OpenOptions::new().write(true).create(true).open(name)?;
-                let contents = self.row_contents
-                    .iter()
-                    .map(|item| item.row_content.as_str())
-                    .collect::<Vec<&str>>()
-                    .join("\n");
-                let contents_u8 = contents.as_bytes();
-                file.set_len(contents.len() as u64)?;
-                file.write_all(contents_u8)?;
-                Ok(contents_u8.len())
+                let len = atomic_save::save(name, |file| self.format.write(file, self.text.chunks(), self.empty))?;
+                self.format.mixed = false;
+                self.saved_format = self.format;
+                Ok(len)
             }
-            None => Err(io::Error::new(io::ErrorKind::Other, "no file")),
+            None => Err(io::Error::other("no file")),
+        }
+    }
+    // char index of a cursor position
+    pub fn char_index(&self, (x, y): Position) -> usize {
+        let row = self.get_editor_row(y);
+        self.text.line_to_char(y) + row.row_content[..row.byte_index(x)].chars().count()
+    }
+    fn position_of(&self, char_index: usize) -> Position {
+        let y = self.text.char_to_line(char_index);
+        let start = self.text.line_to_char(y);
+        let x = self.text.slice(start..char_index).to_string().graphemes(true).count();
+        (x, y)
+    }
+    // `text` may span several rows, returns the position after it
+    pub fn insert_text(&mut self, at: Position, text: &str) -> Position {
+        if at.1 == self.number_of_rows() {
+            self.insert_row(at.1, String::new());
         }
+        let start = self.char_index(at);
+        self.insert_chars(start, text);
+        self.position_of(start + text.chars().count())
     }
-    pub fn join_adjacent_rows(&mut self, at: usize) {
-        let current_row = self.row_contents.remove(at);
-        let previous_row = self.get_editor_row_mut(at - 1);
-        previous_row.row_content.push_str(&current_row.row_content);
-        Self::render_row(previous_row);
+    // by char offset, for undo / redo
+    pub fn insert_chars(&mut self, start: usize, text: &str) {
+        self.empty = false;
+        let row = self.text.char_to_line(start);
+        self.text.insert(start, text);
+        self.invalidate_syntax(row, 0, text.matches('\n').count());
+    }
+    // back to no rows at all once the text is gone, like a new buffer
+    pub fn clear_if_empty(&mut self) {
+        if self.text.len_chars() == 0 {
+            self.empty = true;
+        }
+    }
+    pub fn get_text(&self, from: Position, to: Position) -> String {
+        self.text.slice(self.char_index(from)..self.char_index(to)).to_string()
+    }
+    // removes the text between `from` and `to` and returns it
+    pub fn delete_text(&mut self, from: Position, to: Position) -> String {
+        self.delete_chars(self.char_index(from)..self.char_index(to))
+    }
+    pub fn delete_chars(&mut self, range: Range<usize>) -> String {
+        let (from, to) = (self.text.char_to_line(range.start), self.text.char_to_line(range.end));
+        let deleted = self.text.slice(range.clone()).to_string();
+        self.text.remove(range);
+        self.invalidate_syntax(from, to - from, 0);
+        deleted
     }
 }
 
@@ -110,7 +265,8 @@ pub struct Row {
     pub row_content: String,
     pub render: String,
     pub highlight: Vec<HighlightType>,
-    pub is_comment: bool,
+    pub state: LineState, // what the row leaves open for the next one
+    pub tab_stop: usize,
 }
 
 impl Row {
@@ -119,33 +275,178 @@ impl Row {
             row_content,
             render,
             highlight: Vec::new(),
-            is_comment: false,
+            state: LineState::Normal,
+            tab_stop: TAB_STOP,
         }
     }
-    pub fn insert_char(&mut self, at: usize, ch: char) {
-        self.row_content.insert(at, ch);
-        EditorRows::render_row(self);
+    // cursor_x counts grapheme clusters
+    pub fn len(&self) -> usize {
+        self.row_content.graphemes(true).count()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.row_content.is_empty()
     }
-    pub fn delete_char(&mut self, at: usize) {
-        self.row_content.remove(at);
-        EditorRows::render_row(self)
+    pub fn grapheme(&self, cursor_x: usize) -> Option<&str> {
+        self.row_content.graphemes(true).nth(cursor_x)
+    }
+    // byte offset of `cursor_x` in `row_content`
+    pub fn byte_index(&self, cursor_x: usize) -> usize {
+        self.row_content
+            .grapheme_indices(true)
+            .nth(cursor_x)
+            .map_or(self.row_content.len(), |(idx, _)| idx)
+    }
+    // screen column of `cursor_x`
+    pub fn get_render_x(&self, cursor_x: usize) -> usize {
+        self.row_content
+            .graphemes(true)
+            .take(cursor_x)
+            .fold(0, |render_x, g| {
+                if g == "\t" {
+                    render_x + self.tab_stop - (render_x % self.tab_stop)
+                } else {
+                    render_x + grapheme_width(g)
+                }
+            })
+    }
+    // byte offset of `cursor_x` in `render`
+    pub fn render_index(&self, cursor_x: usize) -> usize {
+        let mut render_x = 0;
+        self.row_content
+            .graphemes(true)
+            .take(cursor_x)
+            .fold(0, |idx, g| {
+                if g == "\t" {
+                    let spaces = self.tab_stop - (render_x % self.tab_stop);
+                    render_x += spaces;
+                    idx + spaces
+                } else {
+                    render_x += grapheme_width(g);
+                    idx + g.len()
+                }
+            })
+    }
+    // byte offset in `render` of byte `idx` of `row_content`
+    pub fn render_offset(&self, idx: usize) -> usize {
+        self.render_index(self.row_content[..idx].graphemes(true).count())
     }
     // 处理比较长的行
     pub fn get_row_content_x(&self, render_x: usize) -> usize {
         let mut current_row_x = 0;
-        for (cursor_x, ch) in self.row_content.chars().enumerate() {
-            if ch == '\t' {
-                current_row_x += TAB_STOP - 1 - (current_row_x % TAB_STOP);
+        for (cursor_x, g) in self.row_content.graphemes(true).enumerate() {
+            if g == "\t" {
+                current_row_x += self.tab_stop - (current_row_x % self.tab_stop);
+            } else {
+                current_row_x += grapheme_width(g);
             }
-            current_row_x += 1;
             if current_row_x > render_x {
                 return cursor_x;
             }
         }
-        0
+        self.len()
+    }
+    // screen column of the byte `idx` of `render`
+    pub fn render_column(&self, idx: usize) -> usize {
+        self.render[..idx].graphemes(true).map(grapheme_width).sum()
+    }
+    // 自动换行: byte offsets of `render` where each screen line starts, the first one is 0.
+    // with `word` a line breaks after the last blank that fits
+    pub fn wrap(&self, width: usize, word: bool) -> Vec<usize> {
+        let mut starts = vec![0];
+        if width == 0 {
+            return starts;
+        }
+        let mut line_start = 0;
+        let mut column = 0;
+        let mut last_blank = None;
+        for (idx, g) in self.render.grapheme_indices(true) {
+            let g_width = grapheme_width(g);
+            if column + g_width > width && idx > line_start {
+                let at = match last_blank {
+                    Some(at) if word && at > line_start => at,
+                    _ => idx,
+                };
+                starts.push(at);
+                line_start = at;
+                column = display_width(&self.render[at..idx]);
+                last_blank = None;
+                if column + g_width > width && idx > line_start {
+                    // the word alone is too long for the line
+                    starts.push(idx);
+                    line_start = idx;
+                    column = 0;
+                }
+            }
+            column += g_width;
+            if g.chars().all(char::is_whitespace) {
+                last_blank = Some(idx + g.len());
+            }
+        }
+        starts
+    }
+    // bytes of `render` shown between the columns `start` and `start + width`,
+    // plus the blank columns left by a wide character cut at `start`
+    pub fn visible_range(&self, start: usize, width: usize) -> (usize, usize, usize) {
+        let mut column = 0;
+        let mut from = None;
+        let mut padding = 0;
+        for (idx, g) in self.render.grapheme_indices(true) {
+            let g_width = grapheme_width(g);
+            if from.is_none() {
+                if column >= start {
+                    from = Some(idx);
+                } else if column + g_width > start {
+                    padding = column + g_width - start;
+                }
+            }
+            if let Some(from) = from {
+                if column + g_width > start + width {
+                    return (from, idx, padding);
+                }
+            }
+            column += g_width;
+        }
+        let len = self.render.len();
+        (from.unwrap_or(len), len, padding)
     }
 }
 
+// 中文、emoji 占两列
+fn canonical(path: &Path) -> PathBuf {
+    if let Ok(path) = fs::canonicalize(path) {
+        return path;
+    }
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    match (fs::canonicalize(dir), path.file_name()) {
+        (Ok(dir), Some(name)) => dir.join(name),
+        _ => env::current_dir().map(|dir| dir.join(path)).unwrap_or_else(|_| path.to_path_buf()),
+    }
+}
+
+pub fn grapheme_width(g: &str) -> usize {
+    // emoji sequences report the sum of their parts
+    cmp::min(g.width(), 2)
+}
+
+pub fn display_width(s: &str) -> usize {
+    s.graphemes(true).map(grapheme_width).sum()
+}
+
+// the longest prefix of `s` that fits in `width` columns
+pub fn truncate_width(s: &str, width: usize) -> &str {
+    let mut column = 0;
+    for (idx, g) in s.grapheme_indices(true) {
+        column += grapheme_width(g);
+        if column > width {
+            return &s[..idx];
+        }
+    }
+    s
+}
+
 #[derive(Clone,Copy)]
 pub enum HighlightType {
     Normal,
@@ -155,5 +456,71 @@ pub enum HighlightType {
     CharLiteral,
     Comment,
     MultilineComment,
-    Other(Color),
-}
\ No newline at end of file
+    Lifetime,
+    Attribute,
+    Macro,
+    Selection,
+    Keyword,
+    Type,
+    Other(Color), // the fixed color of a keyword group without a scope
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the screen lines of `text` wrapped at `width` columns
+    fn lines(text: &str, width: usize, word: bool) -> Vec<String> {
+        let row = Row::new(text.to_string(), text.to_string());
+        let mut starts = row.wrap(width, word);
+        starts.push(text.len());
+        starts.windows(2).map(|pair| text[pair[0]..pair[1]].to_string()).collect()
+    }
+
+    fn states(editor_rows: &EditorRows) -> Vec<LineState> {
+        editor_rows.syntax_states.clone()
+    }
+
+    #[test]
+    fn highlighting_rechecks_every_changed_row() {
+        let mut editor_rows = EditorRows::from_text("a\nb\nc\nd\ne\nf".to_string(), None);
+        let file: crate::syntax_file::SyntaxFile = toml::from_str(include_str!("../syntaxes/c.toml")).unwrap();
+        editor_rows.set_syntax(Some(Box::new(file)));
+        editor_rows.highlight_rows(6);
+        editor_rows.insert_chars(2, "/*");
+        editor_rows.highlight_rows(2);
+        editor_rows.insert_chars(0, "x");
+        while editor_rows.highlight_rows(6) {}
+        let comment = LineState::Comment(1);
+        assert_eq!(states(&editor_rows), [LineState::Normal, comment, comment, comment, comment, comment]);
+    }
+
+    #[test]
+    fn wrap_at_the_width() {
+        assert_eq!(lines("abcdefgh", 3, false), ["abc", "def", "gh"]);
+        assert_eq!(lines("abcdef", 3, false), ["abc", "def"]);
+        assert_eq!(lines("", 3, false), [""]);
+        assert_eq!(Row::new("abc".to_string(), "abc".to_string()).wrap(0, true), [0]);
+    }
+
+    #[test]
+    fn wrap_after_the_last_blank() {
+        assert_eq!(lines("ab cd ef", 5, true), ["ab ", "cd ef"]);
+        assert_eq!(lines("one two three", 8, true), ["one two ", "three"]);
+        assert_eq!(lines("ab cd ef", 5, false), ["ab cd", " ef"]);
+    }
+
+    #[test]
+    fn a_word_longer_than_the_line_is_cut() {
+        assert_eq!(lines("a abcdefgh", 4, true), ["a ", "abcd", "efgh"]);
+        assert_eq!(lines("abcdefgh ij", 4, true), ["abcd", "efgh", " ij"]);
+    }
+
+    #[test]
+    fn wide_characters_and_graphemes_are_not_split() {
+        // a wide character that doesn't fit in the last column moves to the next line
+        assert_eq!(lines("a中文字", 4, false), ["a中", "文字"]);
+        assert_eq!(lines("e\u{301}e\u{301}e\u{301}", 2, false), ["e\u{301}e\u{301}", "e\u{301}"]);
+        // a character wider than the whole line still takes one
+        assert_eq!(lines("中文", 1, false), ["中", "文"]);
+    }
+}
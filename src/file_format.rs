@@ -0,0 +1,178 @@
+use std::io::{ self, BufWriter, Write };
+
+const BOM: &str = "\u{feff}";
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum LineEnding {
+    Lf,
+    Crlf,
+}
+
+impl LineEnding {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::Crlf => "\r\n",
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "LF",
+            LineEnding::Crlf => "CRLF",
+        }
+    }
+}
+
+// 文件格式: what the file looked like on disk besides its rows, written back the same way
+#[derive(Clone, Copy, PartialEq)]
+pub struct FileFormat {
+    pub line_ending: LineEnding,
+    pub final_newline: bool,
+    pub bom: bool, // UTF-8 byte order mark
+    pub mixed: bool, // the file had both line endings, saving converts it to `line_ending`
+}
+
+impl Default for FileFormat {
+    // new files get Unix line endings and a newline at the end
+    fn default() -> Self {
+        Self { line_ending: LineEnding::Lf, final_newline: true, bom: false, mixed: false }
+    }
+}
+
+impl FileFormat {
+    // the format of a file's bytes and its text with `\n` between the rows and none at the end.
+    // a file that mixes both line endings takes the one of its first row and is marked `mixed`
+    pub fn detect(bytes: Vec<u8>) -> io::Result<(Self, String)> {
+        if bytes.starts_with(&[0xff, 0xfe]) || bytes.starts_with(&[0xfe, 0xff]) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "UTF-16 files are not supported"));
+        }
+        let mut text = String::from_utf8(bytes)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "not UTF-8 text"))?;
+        if text.is_empty() {
+            return Ok((FileFormat::default(), text));
+        }
+        let bom = text.starts_with(BOM);
+        if bom {
+            text.drain(..BOM.len());
+        }
+        let line_ending = match text.find('\n') {
+            Some(at) if text[..at].ends_with('\r') => LineEnding::Crlf,
+            _ => LineEnding::Lf,
+        };
+        let crlf = text.matches("\r\n").count();
+        let mixed = crlf > 0 && crlf < text.matches('\n').count();
+        if crlf > 0 {
+            text = text.replace("\r\n", "\n");
+        }
+        let final_newline = text.ends_with('\n');
+        if final_newline {
+            text.pop();
+        }
+        Ok((Self { line_ending, final_newline, bom, mixed }, text))
+    }
+
+    // the rows of `text` as they go to disk, returns the number of bytes
+    pub fn write<'a>(&self, out: &mut impl Write, text: impl Iterator<Item = &'a str>, empty: bool) -> io::Result<usize> {
+        let mut out = BufWriter::new(out);
+        let mut len = 0;
+        let mut put = |bytes: &[u8]| {
+            len += bytes.len();
+            out.write_all(bytes)
+        };
+        if self.bom {
+            put(BOM.as_bytes())?;
+        }
+        for chunk in text {
+            match self.line_ending {
+                LineEnding::Lf => put(chunk.as_bytes())?,
+                LineEnding::Crlf => put(chunk.replace('\n', "\r\n").as_bytes())?,
+            }
+        }
+        if self.final_newline && !empty {
+            put(self.line_ending.as_str().as_bytes())?;
+        }
+        out.flush()?;
+        Ok(len)
+    }
+
+    // `LF`, `CRLF BOM`, `LF noeol`, `mixed CRLF`
+    pub fn describe(&self) -> String {
+        let mut description = self.line_ending.name().to_string();
+        if self.mixed {
+            description.insert_str(0, "mixed ");
+        }
+        if self.bom {
+            description.push_str(" BOM");
+        }
+        if !self.final_newline {
+            description.push_str(" noeol");
+        }
+        description
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the file read and written back unchanged, with the format and text in between
+    fn round_trip(bytes: &[u8]) -> (FileFormat, String) {
+        let (format, text) = FileFormat::detect(bytes.to_vec()).unwrap();
+        let mut out = Vec::new();
+        let len = format.write(&mut out, text.split_inclusive('\n'), bytes.is_empty()).unwrap();
+        assert_eq!(out, bytes);
+        assert_eq!(len, bytes.len());
+        (format, text)
+    }
+
+    #[test]
+    fn lf_and_crlf() {
+        let (format, text) = round_trip(b"a\nb\n");
+        assert!(format == FileFormat::default());
+        assert_eq!(text, "a\nb");
+        let (format, text) = round_trip(b"a\r\nb\r\n");
+        assert!(format.line_ending == LineEnding::Crlf && format.final_newline && !format.mixed);
+        assert_eq!(text, "a\nb");
+        assert_eq!(format.describe(), "CRLF");
+    }
+
+    #[test]
+    fn bom() {
+        let (format, text) = round_trip("\u{feff}a\r\n".as_bytes());
+        assert!(format.bom && format.line_ending == LineEnding::Crlf);
+        assert_eq!(text, "a");
+        assert_eq!(format.describe(), "CRLF BOM");
+    }
+
+    #[test]
+    fn no_final_newline() {
+        let (format, text) = round_trip(b"a\r\nb");
+        assert!(!format.final_newline);
+        assert_eq!(text, "a\nb");
+        assert_eq!(format.describe(), "CRLF noeol");
+        let (format, text) = round_trip(b"");
+        assert!(format == FileFormat::default());
+        assert_eq!(text, "");
+        let (format, text) = round_trip(b"\n");
+        assert!(format.final_newline);
+        assert_eq!(text, "");
+    }
+
+    #[test]
+    fn mixed_line_endings_take_the_first_one() {
+        let (format, text) = FileFormat::detect(b"a\nb\r\nc\n".to_vec()).unwrap();
+        assert!(format.mixed && format.line_ending == LineEnding::Lf);
+        assert_eq!(text, "a\nb\nc");
+        assert_eq!(format.describe(), "mixed LF");
+        let mut out = Vec::new();
+        format.write(&mut out, text.split_inclusive('\n'), false).unwrap();
+        assert_eq!(out, b"a\nb\nc\n");
+    }
+
+    #[test]
+    fn other_encodings_are_rejected() {
+        assert!(FileFormat::detect(vec![0xff, 0xfe, b'a', 0]).is_err());
+        assert!(FileFormat::detect(vec![b'a', 0xc3]).is_err());
+    }
+}
@@ -0,0 +1,49 @@
+use serde::Deserialize;
+
+// 行号
+#[derive(Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LineNumbers {
+    Off,
+    Absolute,
+    Relative,
+    // the cursor row shows its number, the others their distance to it
+    Hybrid,
+}
+
+impl LineNumbers {
+    pub fn next(self) -> Self {
+        match self {
+            LineNumbers::Off => LineNumbers::Absolute,
+            LineNumbers::Absolute => LineNumbers::Relative,
+            LineNumbers::Relative => LineNumbers::Hybrid,
+            LineNumbers::Hybrid => LineNumbers::Off,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            LineNumbers::Off => "off",
+            LineNumbers::Absolute => "absolute",
+            LineNumbers::Relative => "relative",
+            LineNumbers::Hybrid => "hybrid",
+        }
+    }
+
+    // the digits of the largest number plus one blank column
+    pub fn width(self, number_of_rows: usize) -> usize {
+        if self == LineNumbers::Off {
+            return 0;
+        }
+        number_of_rows.max(1).to_string().len() + 1
+    }
+
+    // the number shown in front of `file_row`, counted from 1
+    pub fn label(self, file_row: usize, cursor_y: usize) -> usize {
+        match self {
+            LineNumbers::Relative => file_row.abs_diff(cursor_y),
+            LineNumbers::Hybrid if file_row != cursor_y => file_row.abs_diff(cursor_y),
+            _ => file_row + 1,
+        }
+    }
+}
@@ -0,0 +1,273 @@
+// 撤销 / 重做
+// (x, y): x is the grapheme index inside `row_content`, y is the row index.
+pub type Position = (usize, usize);
+
+// `at` is a char offset into the whole text. a grapheme position can't point between a letter
+// and a combining mark typed after it
+#[derive(Clone, Debug)]
+pub enum EditOp {
+    Insert { at: usize, text: String },
+    Delete { at: usize, text: String },
+}
+
+pub struct Transaction {
+    pub id: u64,
+    pub ops: Vec<EditOp>,
+    pub cursor_before: Position,
+    pub cursor_after: Position,
+    pub empty_before: bool, // the buffer had no rows at all, undoing leaves none again
+}
+
+pub struct History {
+    undo_stack: Vec<Transaction>,
+    redo_stack: Vec<Transaction>,
+    // the top of `undo_stack` still accepts merged edits
+    open: bool,
+    // > 0 while inside `begin_group` / `end_group`
+    group_depth: usize,
+    next_id: u64,
+    saved_id: u64,
+}
+
+impl History {
+    pub fn new() -> Self {
+        Self {
+            undo_stack: Vec::new(),
+            redo_stack: Vec::new(),
+            open: false,
+            group_depth: 0,
+            next_id: 1,
+            saved_id: 0,
+        }
+    }
+
+    pub fn record(&mut self, op: EditOp, cursor_before: Position, cursor_after: Position, empty_before: bool) {
+        self.redo_stack.clear();
+        if let Some(last) = self.undo_stack.last_mut().filter(|_| self.open) {
+            if self.group_depth > 0 {
+                last.ops.push(op);
+                last.cursor_after = cursor_after;
+                return;
+            }
+            if Self::merge(last, &op) {
+                last.cursor_after = cursor_after;
+                return;
+            }
+        }
+        self.undo_stack.push(Transaction {
+            id: self.next_id,
+            ops: vec![op],
+            cursor_before,
+            cursor_after,
+            empty_before,
+        });
+        self.next_id += 1;
+        self.open = true;
+    }
+
+    // typing a word or holding backspace becomes one transaction
+    fn merge(last: &mut Transaction, op: &EditOp) -> bool {
+        if last.ops.len() != 1 {
+            return false;
+        }
+        match (last.ops.last_mut().unwrap(), op) {
+            (EditOp::Insert { at, text }, EditOp::Insert { at: new_at, text: new_text }) => {
+                let mut chars = new_text.chars();
+                let ch = match (chars.next(), chars.next()) {
+                    (Some(ch), None) if ch != '\n' => ch,
+                    _ => return false,
+                };
+                let word_ended = ch.is_whitespace() &&
+                    !text.chars().last().is_none_or(char::is_whitespace);
+                if word_ended || text.contains('\n') || *at + text.chars().count() != *new_at {
+                    return false;
+                }
+                text.push(ch);
+                true
+            }
+            (EditOp::Delete { at, text }, EditOp::Delete { at: new_at, text: new_text }) => {
+                if new_text.contains('\n') || text.contains('\n') {
+                    return false;
+                }
+                if *new_at + new_text.chars().count() == *at {
+                    // backspace
+                    text.insert_str(0, new_text);
+                    *at = *new_at;
+                    true
+                } else if new_at == at {
+                    // delete
+                    text.push_str(new_text);
+                    true
+                } else {
+                    false
+                }
+            }
+            _ => false,
+        }
+    }
+
+    // the next edit starts a new transaction
+    pub fn break_group(&mut self) {
+        if self.group_depth == 0 {
+            self.open = false;
+        }
+    }
+
+    // edits until the matching `end_group` are undone together
+    pub fn begin_group(&mut self) {
+        if self.group_depth == 0 {
+            self.open = false;
+        }
+        self.group_depth += 1;
+    }
+
+    pub fn end_group(&mut self) {
+        self.group_depth = self.group_depth.saturating_sub(1);
+        self.break_group();
+    }
+
+    pub fn undo(&mut self) -> Option<&Transaction> {
+        self.open = false;
+        let transaction = self.undo_stack.pop()?;
+        self.redo_stack.push(transaction);
+        self.redo_stack.last()
+    }
+
+    pub fn redo(&mut self) -> Option<&Transaction> {
+        self.open = false;
+        let transaction = self.redo_stack.pop()?;
+        self.undo_stack.push(transaction);
+        self.undo_stack.last()
+    }
+
+    pub fn mark_saved(&mut self) {
+        self.open = false;
+        self.saved_id = self.current_id();
+    }
+
+    pub fn is_saved(&self) -> bool {
+        self.saved_id == self.current_id()
+    }
+
+    fn current_id(&self) -> u64 {
+        self.undo_stack.last().map_or(0, |transaction| transaction.id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn insert(history: &mut History, at: usize, text: &str) {
+        history.record(EditOp::Insert { at, text: text.to_string() }, (0, 0), (0, 0), false);
+    }
+
+    fn delete(history: &mut History, at: usize, text: &str) {
+        history.record(EditOp::Delete { at, text: text.to_string() }, (0, 0), (0, 0), false);
+    }
+
+    // the text of each op of the transaction undone next
+    fn undo(history: &mut History) -> Vec<String> {
+        let transaction = history.undo().expect("nothing to undo");
+        transaction.ops.iter().map(|op| match op {
+            EditOp::Insert { at, text } => format!("+{}:{}", at, text),
+            EditOp::Delete { at, text } => format!("-{}:{}", at, text),
+        }).collect()
+    }
+
+    #[test]
+    fn typing_merges_until_the_word_ends() {
+        let mut history = History::new();
+        for (at, ch) in "ab cd".chars().enumerate() {
+            insert(&mut history, at, &ch.to_string());
+        }
+        assert_eq!(undo(&mut history), ["+2: cd"]);
+        assert_eq!(undo(&mut history), ["+0:ab"]);
+        assert!(history.undo().is_none());
+    }
+
+    #[test]
+    fn inserts_elsewhere_and_newlines_do_not_merge() {
+        let mut history = History::new();
+        insert(&mut history, 0, "a");
+        insert(&mut history, 5, "b");
+        insert(&mut history, 6, "\n");
+        insert(&mut history, 7, "c");
+        assert_eq!(undo(&mut history), ["+7:c"]);
+        assert_eq!(undo(&mut history), ["+6:\n"]);
+        assert_eq!(undo(&mut history), ["+5:b"]);
+        assert_eq!(undo(&mut history), ["+0:a"]);
+    }
+
+    #[test]
+    fn offsets_count_chars_not_bytes() {
+        let mut history = History::new();
+        insert(&mut history, 0, "e");
+        insert(&mut history, 1, "\u{301}");
+        insert(&mut history, 2, "é");
+        insert(&mut history, 3, "x");
+        assert_eq!(undo(&mut history), ["+0:e\u{301}éx"]);
+    }
+
+    #[test]
+    fn backspace_and_delete_merge() {
+        let mut history = History::new();
+        delete(&mut history, 4, "d");
+        delete(&mut history, 3, "c");
+        delete(&mut history, 2, "b");
+        assert_eq!(undo(&mut history), ["-2:bcd"]);
+        delete(&mut history, 2, "x");
+        delete(&mut history, 2, "y");
+        delete(&mut history, 2, "\n");
+        assert_eq!(undo(&mut history), ["-2:\n"]);
+        assert_eq!(undo(&mut history), ["-2:xy"]);
+    }
+
+    #[test]
+    fn break_group_starts_a_new_transaction() {
+        let mut history = History::new();
+        insert(&mut history, 0, "a");
+        history.break_group();
+        insert(&mut history, 1, "b");
+        assert_eq!(undo(&mut history), ["+1:b"]);
+        assert_eq!(undo(&mut history), ["+0:a"]);
+    }
+
+    #[test]
+    fn a_group_is_undone_together() {
+        let mut history = History::new();
+        insert(&mut history, 0, "a");
+        history.begin_group();
+        delete(&mut history, 0, "a");
+        history.begin_group();
+        insert(&mut history, 0, "b");
+        history.end_group();
+        // a nested `end_group` or a break keeps the outer group open
+        history.break_group();
+        insert(&mut history, 1, "\n");
+        history.end_group();
+        insert(&mut history, 2, "c");
+        assert_eq!(undo(&mut history), ["+2:c"]);
+        assert_eq!(undo(&mut history), ["-0:a", "+0:b", "+1:\n"]);
+        assert_eq!(undo(&mut history), ["+0:a"]);
+    }
+
+    #[test]
+    fn redo_and_saved_state() {
+        let mut history = History::new();
+        assert!(history.is_saved());
+        insert(&mut history, 0, "a");
+        assert!(!history.is_saved());
+        history.mark_saved();
+        // an edit after saving doesn't merge into the saved transaction
+        insert(&mut history, 1, "b");
+        assert!(!history.is_saved());
+        undo(&mut history);
+        assert!(history.is_saved());
+        assert!(matches!(history.redo().unwrap().ops[..], [EditOp::Insert { at: 1, .. }]));
+        assert!(!history.is_saved());
+        undo(&mut history);
+        insert(&mut history, 1, "c");
+        assert!(history.redo().is_none());
+    }
+}
@@ -0,0 +1,577 @@
+use std::collections::BTreeMap;
+use crossterm::event::{ KeyCode, KeyEvent, KeyModifiers };
+
+// 命令: everything a key can be bound to
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Action {
+    Quit,
+    Save,
+    Undo,
+    Redo,
+    Find,
+    Replace,
+    Open,
+    NextBuffer,
+    ListBuffers,
+    ToggleLineNumbers,
+    ToggleWrap,
+    Copy,
+    Cut,
+    Paste,
+    ClearSelection,
+    MoveUp,
+    MoveDown,
+    MoveLeft,
+    MoveRight,
+    MoveWordLeft,
+    MoveWordRight,
+    LineStart,
+    LineEnd,
+    PageUp,
+    PageDown,
+    SelectUp,
+    SelectDown,
+    SelectLeft,
+    SelectRight,
+    SelectWordLeft,
+    SelectWordRight,
+    SelectLineStart,
+    SelectLineEnd,
+    SelectPageUp,
+    SelectPageDown,
+    DeleteBack,
+    DeleteForward,
+    Newline,
+    InsertTab,
+    SplitBelow,
+    SplitRight,
+    NextWindow,
+    CloseWindow,
+    OnlyWindow,
+    GrowHeight,
+    ShrinkHeight,
+    GrowWidth,
+    ShrinkWidth,
+    KillLine,
+    Yank,
+    YankPop,
+    SetMark,
+    IsearchForward,
+    IsearchBackward,
+    ToggleLineEnding,
+    ToggleFinalNewline,
+    ToggleBom,
+}
+
+impl Action {
+    const NAMES: [(Action, &'static str); 57] = [
+        (Action::Quit, "quit"),
+        (Action::Save, "save"),
+        (Action::Undo, "undo"),
+        (Action::Redo, "redo"),
+        (Action::Find, "find"),
+        (Action::Replace, "replace"),
+        (Action::Open, "open"),
+        (Action::NextBuffer, "next-buffer"),
+        (Action::ListBuffers, "list-buffers"),
+        (Action::ToggleLineNumbers, "toggle-line-numbers"),
+        (Action::ToggleWrap, "toggle-wrap"),
+        (Action::Copy, "copy"),
+        (Action::Cut, "cut"),
+        (Action::Paste, "paste"),
+        (Action::ClearSelection, "clear-selection"),
+        (Action::MoveUp, "move-up"),
+        (Action::MoveDown, "move-down"),
+        (Action::MoveLeft, "move-left"),
+        (Action::MoveRight, "move-right"),
+        (Action::MoveWordLeft, "move-word-left"),
+        (Action::MoveWordRight, "move-word-right"),
+        (Action::LineStart, "line-start"),
+        (Action::LineEnd, "line-end"),
+        (Action::PageUp, "page-up"),
+        (Action::PageDown, "page-down"),
+        (Action::SelectUp, "select-up"),
+        (Action::SelectDown, "select-down"),
+        (Action::SelectLeft, "select-left"),
+        (Action::SelectRight, "select-right"),
+        (Action::SelectWordLeft, "select-word-left"),
+        (Action::SelectWordRight, "select-word-right"),
+        (Action::SelectLineStart, "select-line-start"),
+        (Action::SelectLineEnd, "select-line-end"),
+        (Action::SelectPageUp, "select-page-up"),
+        (Action::SelectPageDown, "select-page-down"),
+        (Action::DeleteBack, "delete-back"),
+        (Action::DeleteForward, "delete-forward"),
+        (Action::Newline, "newline"),
+        (Action::InsertTab, "insert-tab"),
+        (Action::SplitBelow, "split-below"),
+        (Action::SplitRight, "split-right"),
+        (Action::NextWindow, "next-window"),
+        (Action::CloseWindow, "close-window"),
+        (Action::OnlyWindow, "only-window"),
+        (Action::GrowHeight, "grow-height"),
+        (Action::ShrinkHeight, "shrink-height"),
+        (Action::GrowWidth, "grow-width"),
+        (Action::ShrinkWidth, "shrink-width"),
+        (Action::KillLine, "kill-line"),
+        (Action::Yank, "yank"),
+        (Action::YankPop, "yank-pop"),
+        (Action::SetMark, "set-mark"),
+        (Action::IsearchForward, "isearch-forward"),
+        (Action::IsearchBackward, "isearch-backward"),
+        (Action::ToggleLineEnding, "toggle-line-ending"),
+        (Action::ToggleFinalNewline, "toggle-final-newline"),
+        (Action::ToggleBom, "toggle-bom"),
+    ];
+
+    pub fn name(self) -> &'static str {
+        Action::NAMES.iter().find(|(action, _)| *action == self).map_or("", |(_, name)| name)
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        Action::NAMES.iter().find(|(_, it)| *it == name).map(|(action, _)| *action)
+    }
+
+    // (key, extend the selection) for the cursor movements
+    pub fn movement(self) -> Option<(KeyCode, bool)> {
+        let movement = match self {
+            Action::MoveUp => (KeyCode::Up, false),
+            Action::MoveDown => (KeyCode::Down, false),
+            Action::MoveLeft => (KeyCode::Left, false),
+            Action::MoveRight => (KeyCode::Right, false),
+            Action::LineStart => (KeyCode::Home, false),
+            Action::LineEnd => (KeyCode::End, false),
+            Action::PageUp => (KeyCode::PageUp, false),
+            Action::PageDown => (KeyCode::PageDown, false),
+            Action::SelectUp => (KeyCode::Up, true),
+            Action::SelectDown => (KeyCode::Down, true),
+            Action::SelectLeft => (KeyCode::Left, true),
+            Action::SelectRight => (KeyCode::Right, true),
+            Action::SelectLineStart => (KeyCode::Home, true),
+            Action::SelectLineEnd => (KeyCode::End, true),
+            Action::SelectPageUp => (KeyCode::PageUp, true),
+            Action::SelectPageDown => (KeyCode::PageDown, true),
+            _ => return None,
+        };
+        Some(movement)
+    }
+}
+
+const DEFAULT_BINDINGS: [(&str, Action); 53] = [
+    ("C-q", Action::Quit),
+    ("C-s", Action::Save),
+    ("C-z", Action::Undo),
+    ("C-y", Action::Redo),
+    ("C-f", Action::Find),
+    ("C-r", Action::Replace),
+    ("C-o", Action::Open),
+    ("C-n", Action::NextBuffer),
+    ("C-b", Action::ListBuffers),
+    ("C-l", Action::ToggleLineNumbers),
+    ("M-z", Action::ToggleWrap),
+    ("M-l", Action::ToggleLineEnding),
+    ("M-n", Action::ToggleFinalNewline),
+    ("M-u", Action::ToggleBom),
+    ("C-c", Action::Copy),
+    ("C-x", Action::Cut),
+    ("C-v", Action::Paste),
+    ("Esc", Action::ClearSelection),
+    ("Up", Action::MoveUp),
+    ("Down", Action::MoveDown),
+    ("Left", Action::MoveLeft),
+    ("Right", Action::MoveRight),
+    ("C-Left", Action::MoveWordLeft),
+    ("C-Right", Action::MoveWordRight),
+    ("Home", Action::LineStart),
+    ("End", Action::LineEnd),
+    ("PageUp", Action::PageUp),
+    ("PageDown", Action::PageDown),
+    ("S-Up", Action::SelectUp),
+    ("S-Down", Action::SelectDown),
+    ("S-Left", Action::SelectLeft),
+    ("S-Right", Action::SelectRight),
+    ("C-S-Left", Action::SelectWordLeft),
+    ("C-S-Right", Action::SelectWordRight),
+    ("S-Home", Action::SelectLineStart),
+    ("S-End", Action::SelectLineEnd),
+    ("S-PageUp", Action::SelectPageUp),
+    ("S-PageDown", Action::SelectPageDown),
+    ("Backspace", Action::DeleteBack),
+    ("Delete", Action::DeleteForward),
+    ("Enter", Action::Newline),
+    ("Tab", Action::InsertTab),
+    ("C-w s", Action::SplitBelow),
+    ("C-w v", Action::SplitRight),
+    ("C-w w", Action::NextWindow),
+    ("C-w C-w", Action::NextWindow),
+    ("C-w c", Action::CloseWindow),
+    ("C-w q", Action::CloseWindow),
+    ("C-w o", Action::OnlyWindow),
+    ("C-w +", Action::GrowHeight),
+    ("C-w -", Action::ShrinkHeight),
+    ("C-w >", Action::GrowWidth),
+    ("C-w <", Action::ShrinkWidth),
+];
+
+// `keymap = "emacs"`, replaces the defaults it collides with, e.g. `C-x` and the `C-w` windows
+const EMACS_BINDINGS: [(&str, Action); 36] = [
+    ("C-f", Action::MoveRight),
+    ("C-b", Action::MoveLeft),
+    ("C-n", Action::MoveDown),
+    ("C-p", Action::MoveUp),
+    ("C-a", Action::LineStart),
+    ("C-e", Action::LineEnd),
+    ("M-f", Action::MoveWordRight),
+    ("M-b", Action::MoveWordLeft),
+    ("C-v", Action::PageDown),
+    ("M-v", Action::PageUp),
+    ("C-d", Action::DeleteForward),
+    ("C-k", Action::KillLine),
+    ("C-w", Action::Cut),
+    ("M-w", Action::Copy),
+    ("C-y", Action::Yank),
+    ("M-y", Action::YankPop),
+    ("C-Space", Action::SetMark),
+    ("C-g", Action::ClearSelection),
+    ("C-s", Action::IsearchForward),
+    ("C-r", Action::IsearchBackward),
+    ("M-%", Action::Replace),
+    ("C-x u", Action::Undo),
+    ("C-x C-s", Action::Save),
+    ("C-x C-c", Action::Quit),
+    ("C-x C-f", Action::Open),
+    ("C-x b", Action::ListBuffers),
+    ("C-x Right", Action::NextBuffer),
+    ("C-x 2", Action::SplitBelow),
+    ("C-x 3", Action::SplitRight),
+    ("C-x o", Action::NextWindow),
+    ("C-x 0", Action::CloseWindow),
+    ("C-x 1", Action::OnlyWindow),
+    ("C-x ^", Action::GrowHeight),
+    ("C-x -", Action::ShrinkHeight),
+    ("C-x }", Action::GrowWidth),
+    ("C-x {", Action::ShrinkWidth),
+];
+
+// the commands of the help line with the keys they are bound to, unbound ones are left out
+const HELP: [(Action, &str); 22] = [
+    (Action::Quit, "Quit"),
+    (Action::Save, "Save"),
+    (Action::Find, "Find"),
+    (Action::IsearchForward, "Search"),
+    (Action::Replace, "Replace"),
+    (Action::Undo, "Undo"),
+    (Action::Redo, "Redo"),
+    (Action::Copy, "Copy"),
+    (Action::Cut, "Cut"),
+    (Action::Paste, "Paste"),
+    (Action::SetMark, "Mark"),
+    (Action::KillLine, "Kill line"),
+    (Action::Yank, "Yank"),
+    (Action::Open, "Open"),
+    (Action::NextBuffer, "Next buffer"),
+    (Action::ListBuffers, "Buffers"),
+    (Action::NextWindow, "Next window"),
+    (Action::ToggleLineNumbers, "Line numbers"),
+    (Action::ToggleWrap, "Wrap"),
+    (Action::ToggleLineEnding, "Line ending"),
+    (Action::ToggleFinalNewline, "Final newline"),
+    (Action::ToggleBom, "BOM"),
+];
+
+// shift is part of the character itself
+pub fn normalize(key: KeyEvent) -> KeyEvent {
+    match key.code {
+        KeyCode::Char(_) | KeyCode::BackTab => KeyEvent::new(key.code, key.modifiers - KeyModifiers::SHIFT),
+        _ => key,
+    }
+}
+
+// `C-x`, `M-f`, `S-Up`, `Ctrl-Alt-Delete`, `Space`, ...
+fn parse_key(spec: &str) -> Option<KeyEvent> {
+    let mut modifiers = KeyModifiers::NONE;
+    let mut rest = spec;
+    'prefixes: loop {
+        for (prefix, modifier) in [
+            ("C-", KeyModifiers::CONTROL),
+            ("Ctrl-", KeyModifiers::CONTROL),
+            ("M-", KeyModifiers::ALT),
+            ("Alt-", KeyModifiers::ALT),
+            ("S-", KeyModifiers::SHIFT),
+            ("Shift-", KeyModifiers::SHIFT),
+        ] {
+            // `C--` is Ctrl and `-`
+            if rest.len() > prefix.len() && rest.get(..prefix.len()).is_some_and(|head| head.eq_ignore_ascii_case(prefix)) {
+                modifiers |= modifier;
+                rest = &rest[prefix.len()..];
+                continue 'prefixes;
+            }
+        }
+        break;
+    }
+    let mut chars = rest.chars();
+    let code = match (chars.next(), chars.next()) {
+        (Some(ch), None) => KeyCode::Char(ch),
+        _ => match rest.to_ascii_lowercase().as_str() {
+            "up" => KeyCode::Up,
+            "down" => KeyCode::Down,
+            "left" => KeyCode::Left,
+            "right" => KeyCode::Right,
+            "home" => KeyCode::Home,
+            "end" => KeyCode::End,
+            "pageup" => KeyCode::PageUp,
+            "pagedown" => KeyCode::PageDown,
+            "enter" | "ret" => KeyCode::Enter,
+            "tab" => KeyCode::Tab,
+            "backtab" => KeyCode::BackTab,
+            "backspace" => KeyCode::Backspace,
+            "delete" | "del" => KeyCode::Delete,
+            "insert" => KeyCode::Insert,
+            "esc" => KeyCode::Esc,
+            "space" | "spc" => KeyCode::Char(' '),
+            name => KeyCode::F(name.strip_prefix('f')?.parse().ok().filter(|n| (1..=12).contains(n))?),
+        },
+    };
+    let key = match code {
+        // `S-a` is `A`
+        KeyCode::Char(ch) if modifiers.contains(KeyModifiers::SHIFT) => {
+            KeyEvent::new(KeyCode::Char(ch.to_ascii_uppercase()), modifiers)
+        }
+        _ => KeyEvent::new(code, modifiers),
+    };
+    Some(normalize(key))
+}
+
+// keys separated by blanks, `C-x C-s`
+fn parse_keys(spec: &str) -> Option<Vec<KeyEvent>> {
+    let keys = spec.split_whitespace().map(parse_key).collect::<Option<Vec<_>>>()?;
+    Some(keys).filter(|keys| !keys.is_empty())
+}
+
+pub fn key_name(key: &KeyEvent) -> String {
+    let mut name = String::new();
+    for (modifier, prefix) in [(KeyModifiers::CONTROL, "C-"), (KeyModifiers::ALT, "M-"), (KeyModifiers::SHIFT, "S-")] {
+        if key.modifiers.contains(modifier) {
+            name.push_str(prefix);
+        }
+    }
+    match key.code {
+        KeyCode::Char(' ') => name.push_str("Space"),
+        KeyCode::Char(ch) => name.push(ch),
+        KeyCode::F(n) => name.push_str(&format!("F{}", n)),
+        code => name.push_str(&format!("{:?}", code)),
+    }
+    name
+}
+
+pub fn keys_name(keys: &[KeyEvent]) -> String {
+    keys.iter().map(key_name).collect::<Vec<_>>().join(" ")
+}
+
+pub enum Lookup {
+    Action(Action),
+    Prefix, // more keys to come
+    Unbound,
+}
+
+// 按键绑定
+pub struct Keymap {
+    bindings: Vec<(Vec<KeyEvent>, Action)>,
+    modal: bool, // `keymap = "vim"`, starts in Normal mode and Esc leads back to it
+}
+
+impl Keymap {
+    pub const PROFILES: [&'static str; 3] = ["default", "emacs", "vim"];
+
+    // the defaults, then the bindings of the `profile` and the `[keys]` of the config on top,
+    // an action of "none" removes a binding. "vim" keeps the defaults and adds the modal layer.
+    // returns the problems found, a binding shadowed by a longer or shorter one is a conflict
+    pub fn new(profile: &str, keys: &BTreeMap<String, String>) -> (Self, Vec<String>) {
+        let mut keymap = Keymap { bindings: Vec::new(), modal: profile == "vim" };
+        for (spec, action) in DEFAULT_BINDINGS {
+            keymap.bindings.push((parse_keys(spec).unwrap(), action));
+        }
+        if profile == "emacs" {
+            for (spec, action) in EMACS_BINDINGS {
+                let chord = parse_keys(spec).unwrap();
+                keymap.bindings.retain(|(keys, _)| !keys.starts_with(&chord) && !chord.starts_with(keys));
+                keymap.bindings.push((chord, action));
+            }
+        }
+        let mut errors = Vec::new();
+        let mut configured: Vec<(Vec<KeyEvent>, &str)> = Vec::new();
+        for (spec, name) in keys {
+            let chord = match parse_keys(spec) {
+                Some(chord) => chord,
+                None => {
+                    errors.push(format!("bad key `{}`", spec));
+                    continue;
+                }
+            };
+            let action = match name.as_str() {
+                "none" => None,
+                name => match Action::from_name(name) {
+                    Some(action) => Some(action),
+                    None => {
+                        errors.push(format!("unknown action `{}` for `{}`", name, spec));
+                        continue;
+                    }
+                },
+            };
+            // the same keys spelled twice
+            if let Some((_, other)) = configured.iter().find(|(keys, _)| *keys == chord) {
+                errors.push(format!("`{}` and `{}` are the same keys", other, spec));
+            }
+            configured.push((chord.clone(), spec));
+            keymap.bindings.retain(|(keys, _)| *keys != chord);
+            let action = match action {
+                Some(action) => action,
+                None => continue,
+            };
+            let shadowed: Vec<_> = keymap.bindings
+                .iter()
+                .filter(|(keys, _)| keys.starts_with(&chord) || chord.starts_with(keys))
+                .map(|(keys, action)| format!("`{}` ({})", keys_name(keys), action.name()))
+                .collect();
+            if !shadowed.is_empty() {
+                errors.push(format!("`{}` ({}) conflicts with {}", spec, action.name(), shadowed.join(", ")));
+                keymap.bindings.retain(|(keys, _)| !keys.starts_with(&chord) && !chord.starts_with(keys));
+            }
+            keymap.bindings.push((chord, action));
+        }
+        (keymap, errors)
+    }
+
+    pub fn modal(&self) -> bool {
+        self.modal
+    }
+
+    // the keys bound last to `action`, for messages
+    pub fn binding(&self, action: Action) -> Option<String> {
+        self.bindings.iter().rev().find(|(_, it)| *it == action).map(|(keys, _)| keys_name(keys))
+    }
+
+    // `HELP: C-q = Quit | C-s = Save | ...`, shown at startup
+    pub fn help(&self) -> String {
+        let keys: Vec<_> = HELP
+            .iter()
+            .filter_map(|(action, label)| Some(format!("{} = {}", self.binding(*action)?, label)))
+            .collect();
+        format!("HELP: {}", keys.join(" | "))
+    }
+
+    pub fn lookup(&self, keys: &[KeyEvent]) -> Lookup {
+        let mut prefix = false;
+        for (chord, action) in &self.bindings {
+            if chord == keys {
+                return Lookup::Action(*action);
+            }
+            prefix |= chord.starts_with(keys);
+        }
+        if prefix { Lookup::Prefix } else { Lookup::Unbound }
+    }
+
+    // what may follow `keys`, e.g. `s = split-below | w = next-window`
+    pub fn continuations(&self, keys: &[KeyEvent]) -> String {
+        let mut next: Vec<(String, &str)> = Vec::new();
+        for (chord, action) in &self.bindings {
+            if chord.len() > keys.len() && chord.starts_with(keys) {
+                let key = key_name(&chord[keys.len()]);
+                let action = if chord.len() == keys.len() + 1 { action.name() } else { "..." };
+                if !next.iter().any(|(it, _)| *it == key) {
+                    next.push((key, action));
+                }
+            }
+        }
+        next.iter().map(|(key, action)| format!("{} = {}", key, action)).collect::<Vec<_>>().join(" | ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(code: KeyCode, modifiers: KeyModifiers) -> Option<KeyEvent> {
+        Some(KeyEvent::new(code, modifiers))
+    }
+
+    fn configured(keys: &[(&str, &str)]) -> (Keymap, Vec<String>) {
+        let keys = keys.iter().map(|(spec, action)| (spec.to_string(), action.to_string())).collect();
+        Keymap::new("default", &keys)
+    }
+
+    #[test]
+    fn parse_key_prefixes_and_names() {
+        assert_eq!(parse_key("C-x"), key(KeyCode::Char('x'), KeyModifiers::CONTROL));
+        assert_eq!(parse_key("ctrl-alt-Delete"), key(KeyCode::Delete, KeyModifiers::CONTROL | KeyModifiers::ALT));
+        assert_eq!(parse_key("M-f"), key(KeyCode::Char('f'), KeyModifiers::ALT));
+        assert_eq!(parse_key("S-Up"), key(KeyCode::Up, KeyModifiers::SHIFT));
+        assert_eq!(parse_key("C--"), key(KeyCode::Char('-'), KeyModifiers::CONTROL));
+        assert_eq!(parse_key("C-Space"), key(KeyCode::Char(' '), KeyModifiers::CONTROL));
+        assert_eq!(parse_key("F12"), key(KeyCode::F(12), KeyModifiers::NONE));
+        assert_eq!(parse_key("ret"), key(KeyCode::Enter, KeyModifiers::NONE));
+        assert_eq!(parse_key("-"), key(KeyCode::Char('-'), KeyModifiers::NONE));
+    }
+
+    #[test]
+    fn parse_key_drops_shift_of_characters() {
+        assert_eq!(parse_key("S-a"), key(KeyCode::Char('A'), KeyModifiers::NONE));
+        assert_eq!(parse_key("C-S-a"), parse_key("C-A"));
+    }
+
+    #[test]
+    fn parse_key_rejects_unknown_keys() {
+        assert_eq!(parse_key("F13"), None);
+        assert_eq!(parse_key("C-"), None);
+        assert_eq!(parse_key("Hyper-x"), None);
+        assert_eq!(parse_key(""), None);
+        assert_eq!(parse_keys("C-x  C-s").map(|keys| keys.len()), Some(2));
+        assert_eq!(parse_keys(" "), None);
+        assert_eq!(parse_keys("C-x bogus"), None);
+    }
+
+    #[test]
+    fn config_keys_replace_the_defaults() {
+        let (keymap, errors) = configured(&[("C-s", "quit"), ("F2", "save"), ("C-q", "none")]);
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert_eq!(keymap.binding(Action::Quit).as_deref(), Some("C-s"));
+        assert_eq!(keymap.binding(Action::Save).as_deref(), Some("F2"));
+        assert!(matches!(keymap.lookup(&parse_keys("C-q").unwrap()), Lookup::Unbound));
+        // Esc clears the selection, only the vim profile has modes
+        assert!(!keymap.modal());
+        assert!(matches!(keymap.lookup(&parse_keys("Esc").unwrap()), Lookup::Action(Action::ClearSelection)));
+        assert!(Keymap::new("vim", &BTreeMap::new()).0.modal());
+        let help = keymap.help();
+        assert!(help.starts_with("HELP: C-s = Quit | F2 = Save | C-f = Find | C-r = Replace"), "{}", help);
+        assert!(help.ends_with("M-l = Line ending | M-n = Final newline | M-u = BOM"), "{}", help);
+    }
+
+    #[test]
+    fn conflicts_are_reported() {
+        // `C-w` is the prefix of the window commands
+        let (keymap, errors) = configured(&[("C-w", "quit")]);
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].starts_with("`C-w` (quit) conflicts with `C-w s` (split-below)"), "{}", errors[0]);
+        assert!(matches!(keymap.lookup(&parse_keys("C-w").unwrap()), Lookup::Action(Action::Quit)));
+        // and a longer chord shadows a shorter one
+        let (_, errors) = configured(&[("C-s x", "quit")]);
+        assert_eq!(errors, ["`C-s x` (quit) conflicts with `C-s` (save)"]);
+        let (_, errors) = configured(&[("C-a", "save"), ("Ctrl-a", "quit")]);
+        assert!(errors.iter().any(|err| err.contains("are the same keys")), "{:?}", errors);
+        let (_, errors) = configured(&[("C-x", "nope"), ("Hyper-x", "save")]);
+        assert_eq!(errors, ["unknown action `nope` for `C-x`", "bad key `Hyper-x`"]);
+    }
+
+    #[test]
+    fn emacs_profile_replaces_colliding_defaults() {
+        let (keymap, errors) = Keymap::new("emacs", &BTreeMap::new());
+        assert!(errors.is_empty());
+        assert!(!keymap.modal());
+        assert_eq!(keymap.binding(Action::Save).as_deref(), Some("C-x C-s"));
+        assert!(matches!(keymap.lookup(&parse_keys("C-x").unwrap()), Lookup::Prefix));
+        assert!(matches!(keymap.lookup(&parse_keys("C-w").unwrap()), Lookup::Action(Action::Cut)));
+        let help = keymap.help();
+        assert!(help.starts_with("HELP: C-x C-c = Quit | C-x C-s = Save | C-s = Search | M-% = Replace | C-x u = Undo"), "{}", help);
+        assert!(help.contains("C-w = Cut") && help.contains("C-x o = Next window") && !help.contains("Find"), "{}", help);
+    }
+}
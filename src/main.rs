@@ -1,10 +1,12 @@
 use std::io::{ self, stdout, Write };
-use std::cmp;
-use std::path::PathBuf;
-use std::time::Duration;
+use std::{ cmp, env, mem };
+use std::path::{ Path, PathBuf };
+use std::time::{ Duration, Instant };
 use crossterm::terminal::ClearType;
-use crossterm::{ event, terminal, execute, cursor, queue, style::{ self, Color } };
-use crossterm::event::{ Event, KeyCode, KeyEvent };
+use crossterm::{ event, terminal, execute, cursor, queue };
+use crossterm::event::{ Event, KeyCode, KeyEvent, MouseButton, MouseEvent, MouseEventKind };
+use regex::Regex;
+use unicode_segmentation::UnicodeSegmentation;
 
 mod cursor_xy;
 mod editor_row;
@@ -12,21 +14,49 @@ mod status_message;
 mod prompt;
 mod search_direction;
 mod syntax_struct;
+mod syntax_file;
+mod rust_syntax;
+mod history;
+mod clipboard;
+mod vim;
+mod buffer;
+mod window;
+mod gutter;
+mod screen;
+mod config;
+mod theme;
+mod keymap;
+mod atomic_save;
+mod file_format;
 
-use cursor_xy::CursorController;
+use cursor_xy::{ CursorController, Wrap };
 use editor_row::EditorRows;
 use status_message::StatusMessage;
 use search_direction::*;
+use history::{ History, EditOp, Position };
+use clipboard::Clipboard;
+use vim::{ Vim, Mode, Parsed, Command };
+use buffer::Buffer;
+use window::{ Window, Rect, Layout };
+use gutter::LineNumbers;
+use screen::Screen;
+use config::Config;
+use theme::{ ColorDepth, Style, Theme };
+use keymap::{ Keymap, Action, Lookup };
+use syntax_file::Syntaxes;
+use file_format::LineEnding;
 
-use crate::syntax_struct::SyntaxHighlight;
 use crate::editor_row::HighlightType;
-use crate::editor_row::Row;
 
-const VERSION: &'static str = "0.1.0";
+const VERSION: &str = "0.1.0";
+const DOUBLE_CLICK: Duration = Duration::from_millis(400);
+const SCROLL_LINES: isize = 3; // per wheel step
+const BACKGROUND_ROWS: usize = 200; // highlighted between two looks at the input
 struct CleanUp;
 
 impl Drop for CleanUp {
     fn drop(&mut self) {
+        execute!(stdout(), event::DisableMouseCapture).expect("Could not disable mouse capture");
         terminal::disable_raw_mode().expect("Could not disable raw mode");
         Output::clear_screen().expect("Error");
     }
@@ -35,14 +65,25 @@ impl Drop for CleanUp {
 struct Reader; //read keypress
 
 impl Reader {
-    fn read_key(&self) -> crossterm::Result<KeyEvent> {
+    // key presses, mouse buttons / wheel and terminal resizes
+    fn read_event(&self) -> crossterm::Result<Event> {
         loop {
-            if event::poll(Duration::from_millis(500))? {
-                if let Event::Key(event) = event::read()? {
-                    return Ok(event);
-                }
+            if let Some(event) = self.poll_event(Duration::from_millis(500))? {
+                return Ok(event);
+            }
+        }
+    }
+
+    // None when nothing came in within `timeout`
+    fn poll_event(&self, timeout: Duration) -> crossterm::Result<Option<Event>> {
+        if event::poll(timeout)? {
+            let event = event::read()?;
+            let moved = matches!(event, Event::Mouse(MouseEvent { kind: MouseEventKind::Moved, .. }));
+            if !moved {
+                return Ok(Some(event));
             }
         }
+        Ok(None)
     }
 }
 
@@ -50,119 +91,336 @@ struct Editor {
     reader: Reader,
     output: Output,
     quit_time: u8,
+    vim: Vim,
+    keymap: Keymap,
+    pending_keys: Vec<KeyEvent>, // the start of a multi-key binding like `C-w s`
+    // the action of the key before, `C-k C-k` kills into one entry and `M-y` only follows a yank
+    last_action: Option<Action>,
+    previous_action: Option<Action>,
 }
 
 impl Editor {
     fn new() -> Self {
+        let (config, mut errors) = Config::load();
+        let (keymap, conflicts) = Keymap::new(&config.keymap, &config.keys);
+        errors.extend(conflicts);
+        let (syntaxes, syntax_errors) = Syntaxes::load();
+        errors.extend(syntax_errors);
+        let theme = Theme::load(&config.theme, ColorDepth::detect()).unwrap_or_else(|err| {
+            errors.push(err);
+            Theme::load("default", ColorDepth::detect()).unwrap_or_default()
+        });
+        let output = Output::new(config, syntaxes, theme, keymap.help(), errors);
+        Editor::with_output(output, keymap)
+    }
+
+    // the vim profile starts in Normal mode
+    fn with_output(mut output: Output, keymap: Keymap) -> Self {
+        if keymap.modal() {
+            output.mode = Mode::Normal;
+        }
         Self {
             reader: Reader,
-            output: Output::new(),
-            quit_time: 2,
+            quit_time: output.config.quit_confirmations,
+            output,
+            vim: Vim::new(),
+            keymap,
+            pending_keys: Vec::new(),
+            last_action: None,
+            previous_action: None,
         }
     }
 
     fn process_keypress(&mut self) -> crossterm::Result<bool> {
-        match self.reader.read_key()? {
-            KeyEvent { code: KeyCode::Char('q'), modifiers: event::KeyModifiers::CONTROL } => {
-                if self.output.dirty > 0 && self.quit_time > 0 {
-                    self.output.status_message.set_message(
-                        format!(
-                            "WARING! File unsaved changes. Press Ctrl-Q {} more times to quit. ",
-                            self.quit_time
-                        )
+        match self.output.read_input(&self.reader)? {
+            Event::Mouse(mouse) => {
+                self.process_mouse(mouse);
+                Ok(true)
+            }
+            Event::Key(key) => self.process_key(key),
+            _ => Ok(true),
+        }
+    }
+
+    // clicks, drags and the wheel
+    fn process_mouse(&mut self, mouse: MouseEvent) {
+        let (column, row) = (mouse.column as usize, mouse.row as usize);
+        self.last_action = None;
+        match mouse.kind {
+            MouseEventKind::Down(MouseButton::Left) => {
+                self.pending_keys.clear();
+                self.vim.reset();
+                self.output.click(column, row);
+            }
+            MouseEventKind::Drag(MouseButton::Left) => self.output.drag(column, row),
+            MouseEventKind::Up(MouseButton::Left) => {
+                self.output.drag_from = None;
+            }
+            MouseEventKind::ScrollUp => self.output.scroll_window(column, row, -SCROLL_LINES),
+            MouseEventKind::ScrollDown => self.output.scroll_window(column, row, SCROLL_LINES),
+            _ => {}
+        }
+        vim::clamp_cursor(&mut self.output);
+    }
+
+    // the modal layer, keys it does not handle fall through to `process_default_key`
+    fn process_key(&mut self, key: KeyEvent) -> crossterm::Result<bool> {
+        // any key brings the cursor back into view
+        self.output.cursor_controller.free_scroll = false;
+        self.previous_action = self.last_action.take();
+        // multi-key bindings work in every mode
+        let key = keymap::normalize(key);
+        if !self.pending_keys.is_empty() || matches!(self.keymap.lookup(&[key]), Lookup::Prefix) {
+            return self.process_chord(key);
+        }
+        let esc = key == KeyEvent::new(KeyCode::Esc, event::KeyModifiers::NONE);
+        match self.output.mode {
+            Mode::Insert => {
+                self.vim.record(key);
+                if esc && self.keymap.modal() {
+                    self.vim.leave_insert(&mut self.output);
+                    return Ok(true);
+                }
+            }
+            Mode::Normal | Mode::Visual if esc => {
+                self.vim.reset();
+                self.output.cursor_controller.anchor = None;
+                self.output.mode = Mode::Normal;
+                vim::clamp_cursor(&mut self.output);
+                return Ok(true);
+            }
+            Mode::Normal | Mode::Visual => {
+                match self.vim.feed(key, self.output.mode) {
+                    Parsed::Pending => {
+                        return Ok(true);
+                    }
+                    Parsed::Command(Command::Repeat, count) => {
+                        self.vim.take_keys();
+                        // `3.` is undone as one change
+                        self.output.history.begin_group();
+                        for _ in 0..count.unwrap_or(1) {
+                            for key in self.vim.last_change() {
+                                self.process_key(key)?;
+                            }
+                        }
+                        self.output.history.end_group();
+                        return Ok(true);
+                    }
+                    Parsed::Command(command, count) => {
+                        let keys = self.vim.take_keys();
+                        self.vim.execute(command, count, keys, &mut self.output);
+                        return Ok(true);
+                    }
+                    Parsed::Unhandled => {}
+                }
+            }
+        }
+        let result = self.process_default_key(key);
+        vim::clamp_cursor(&mut self.output);
+        result
+    }
+
+    fn process_chord(&mut self, key: KeyEvent) -> crossterm::Result<bool> {
+        self.pending_keys.push(key);
+        let keys = keymap::keys_name(&self.pending_keys);
+        match self.keymap.lookup(&self.pending_keys) {
+            Lookup::Prefix => {
+                let message = format!("{}: {}", keys, self.keymap.continuations(&self.pending_keys));
+                self.output.status_message.set_message(message);
+                Ok(true)
+            }
+            Lookup::Action(action) => {
+                self.pending_keys.clear();
+                self.output.status_message.set_message(String::new());
+                self.vim.reset();
+                let result = self.run_action(action);
+                vim::clamp_cursor(&mut self.output);
+                result
+            }
+            Lookup::Unbound => {
+                self.pending_keys.clear();
+                self.output.status_message.set_message(format!("{} is not bound", keys));
+                Ok(true)
+            }
+        }
+    }
+
+    fn process_default_key(&mut self, key: KeyEvent) -> crossterm::Result<bool> {
+        match self.keymap.lookup(&[key]) {
+            Lookup::Action(action) => self.run_action(action),
+            _ => {
+                if let KeyEvent { code: KeyCode::Char(ch), modifiers: event::KeyModifiers::NONE } = key {
+                    self.output.inset_char(ch);
+                }
+                Ok(true)
+            }
+        }
+    }
+
+    fn run_action(&mut self, action: Action) -> crossterm::Result<bool> {
+        self.last_action = Some(action);
+        if let Some((code, select)) = action.movement() {
+            self.output.extend_selection(select);
+            match code {
+                KeyCode::PageUp | KeyCode::PageDown => self.output.move_page(code),
+                _ => self.output.move_cursor(code),
+            }
+            return Ok(true);
+        }
+        match action {
+            Action::Quit => {
+                let unsaved = self.output.unsaved_buffers();
+                if !unsaved.is_empty() && self.quit_time > 0 {
+                    let message = format!(
+                        "WARING! Unsaved changes in {}. Press {} {} more times to quit. ",
+                        unsaved.join(", "),
+                        self.keymap.binding(Action::Quit).unwrap_or_default(),
+                        self.quit_time
                     );
+                    self.output.status_message.set_message(message);
                     self.quit_time = self.quit_time.saturating_sub(1);
                     return Ok(true);
                 }
                 return Ok(false);
             }
-            KeyEvent {
-                code: code @ (
-                    KeyCode::Up
-                    | KeyCode::Down
-                    | KeyCode::Left
-                    | KeyCode::Right
-                    | KeyCode::End
-                    | KeyCode::Home
-                ),
-                modifiers: event::KeyModifiers::NONE,
-            } => {
-                self.output.move_cursor(code);
-            }
-            KeyEvent {
-                code: val @ (KeyCode::PageUp | KeyCode::PageDown),
-                modifiers: event::KeyModifiers::NONE,
-            } => {
-                if matches!(val, KeyCode::PageUp) {
-                    self.output.cursor_controller.cursor_y =
-                        self.output.cursor_controller.row_offset;
-                } else {
-                    self.output.cursor_controller.cursor_y = cmp::min(
-                        self.output.win_size.1 + self.output.cursor_controller.row_offset - 1,
-                        self.output.editor_rows.number_of_rows()
-                    );
-                }
-                let key = if matches!(val, KeyCode::PageUp) { KeyCode::Up } else { KeyCode::Down };
-                (0..self.output.win_size.1).for_each(|_| {
-                    self.output.move_cursor(key);
-                });
+            Action::MoveWordLeft | Action::SelectWordLeft | Action::MoveWordRight | Action::SelectWordRight => {
+                self.output.extend_selection(matches!(action, Action::SelectWordLeft | Action::SelectWordRight));
+                self.output.move_word(matches!(action, Action::MoveWordRight | Action::SelectWordRight));
             }
-            KeyEvent { code: KeyCode::Char('s'), modifiers: event::KeyModifiers::CONTROL } => {
-                if matches!(self.output.editor_rows.filename, None) {
+            Action::Save => {
+                // Save as only names the buffer once something was written
+                let save_as = self.output.editor_rows.filename.is_none();
+                if save_as {
                     let prompt: Option<PathBuf> = prompt!(
                         &mut self.output,
                         "Save as : {} (esc to cancel)"
                     ).map(|item| item.into());
-                    if let None = prompt {
+                    if prompt.is_none() {
                         self.output.status_message.set_message("Save Aborted".to_string());
                         return Ok(true);
                     }
-                    prompt
-                        .as_ref()
-                        .and_then(|path| path.extension())
-                        .and_then(|ext| ext.to_str())
-                        .map(|ext| {
-                            Output::select_syntax(ext).map(|syntax| {
-                                let highlight = self.output.syntax_highlight.insert(syntax);
-                                for i in 0..self.output.editor_rows.number_of_rows() {
-                                    highlight.update_syntax(i, &mut self.output.editor_rows.row_contents);
-                                }
-                            });
-                        });
                     self.output.editor_rows.filename = prompt;
+                    if let Some(syntax) = self.output.syntaxes.for_rows(&self.output.editor_rows) {
+                        self.output.editor_rows.set_syntax(Some(syntax));
+                    }
                 }
-                self.output.editor_rows.save().map(|len| {
-                    self.output.status_message.set_message(
-                        format!("{} bytes written to disk.", len)
+                // the rows with the other line ending would change, a second save confirms it
+                let format = self.output.editor_rows.format;
+                if format.mixed && self.previous_action != Some(Action::Save) {
+                    let message = format!(
+                        "Mixed line endings, save again to convert them all to {} ({} picks the other)",
+                        format.line_ending.name(),
+                        self.keymap.binding(Action::ToggleLineEnding).unwrap_or_else(|| "toggle-line-ending".to_string())
                     );
-                    self.output.dirty = 0;
-                })?;
-            }
-            KeyEvent {
-                code: code @ (KeyCode::Char(..) | KeyCode::Tab),
-                modifiers: event::KeyModifiers::NONE | event::KeyModifiers::SHIFT,
-            } => {
-                self.output.inset_char(match code {
-                    KeyCode::Tab => '\t',
-                    KeyCode::Char(ch) => ch,
-                    _ => unreachable!(),
-                });
+                    self.output.status_message.set_message(message);
+                    return Ok(true);
+                }
+                // 保存失败 keeps the buffer and its changes, the message says why
+                match self.output.editor_rows.save() {
+                    Ok(len) => {
+                        self.output.status_message.set_message(format!("{} bytes written to disk.", len));
+                        self.output.history.mark_saved();
+                        self.output.dirty = 0;
+                    }
+                    Err(err) => {
+                        let name = Output::buffer_name(&self.output.editor_rows);
+                        self.output.status_message.set_message(format!("Can't save {}: {}", name, err));
+                        if save_as {
+                            self.output.editor_rows.filename = None;
+                            let syntax = self.output.syntaxes.for_rows(&self.output.editor_rows);
+                            self.output.editor_rows.set_syntax(syntax);
+                        }
+                    }
+                }
             }
-            KeyEvent {
-                code: key @ (KeyCode::Backspace | KeyCode::Delete),
-                modifiers: event::KeyModifiers::NONE,
-            } => {
-                if matches!(key, KeyCode::Delete) {
-                    self.output.move_cursor(KeyCode::Right);
+            Action::Undo => self.output.undo(),
+            Action::Redo => self.output.redo(),
+            Action::InsertTab => self.output.insert_tab(),
+            Action::DeleteBack | Action::DeleteForward => {
+                // a selection goes as a whole, else the character before / under the cursor
+                let deleted = self.output.delete_selection();
+                if !deleted {
+                    if action == Action::DeleteForward {
+                        self.output.move_cursor(KeyCode::Right);
+                    }
+                    self.output.delete_char();
                 }
-                self.output.delete_char();
             }
-            KeyEvent { code: KeyCode::Enter, modifiers: event::KeyModifiers::NONE } => {
-                self.output.insert_newline();
+            Action::Newline => self.output.insert_newline(),
+            Action::Find => self.output.find()?,
+            Action::Replace => self.output.replace()?,
+            Action::Open => self.output.open_file()?,
+            Action::NextBuffer => self.output.next_buffer(),
+            Action::ListBuffers => self.output.pick_buffer()?,
+            Action::ToggleLineNumbers => {
+                self.output.line_numbers = self.output.line_numbers.next();
+                let message = format!("Line numbers: {}", self.output.line_numbers.name());
+                self.output.status_message.set_message(message);
             }
-            KeyEvent { code: KeyCode::Char('f'), modifiers: event::KeyModifiers::CONTROL } => {
-                self.output.find()?;
+            Action::ToggleWrap => {
+                self.output.wrap = self.output.wrap.next();
+                self.output.cursor_controller.wrap = self.output.wrap;
+                let message = format!("Soft wrap: {}", self.output.wrap.name());
+                self.output.status_message.set_message(message);
             }
+            // 转换文件格式, written with the next save
+            Action::ToggleLineEnding | Action::ToggleFinalNewline | Action::ToggleBom => {
+                let format = &mut self.output.editor_rows.format;
+                let message = match action {
+                    // a mixed file is converted to its current one first
+                    Action::ToggleLineEnding if format.mixed => {
+                        format.mixed = false;
+                        format!("Line endings: {}", format.line_ending.name())
+                    }
+                    Action::ToggleLineEnding => {
+                        format.line_ending = match format.line_ending {
+                            LineEnding::Lf => LineEnding::Crlf,
+                            LineEnding::Crlf => LineEnding::Lf,
+                        };
+                        format!("Line endings: {}", format.line_ending.name())
+                    }
+                    Action::ToggleFinalNewline => {
+                        format.final_newline = !format.final_newline;
+                        format!("Final newline: {}", if format.final_newline { "on" } else { "off" })
+                    }
+                    _ => {
+                        format.bom = !format.bom;
+                        format!("Byte order mark: {}", if format.bom { "on" } else { "off" })
+                    }
+                };
+                self.output.status_message.set_message(message);
+                self.output.update_dirty();
+            }
+            Action::Copy => {
+                let copied = self.output.copy_selection();
+                // the region of `C-Space` ends with the copy
+                if copied && self.output.cursor_controller.mark_active {
+                    self.output.clear_selection();
+                }
+            }
+            Action::Cut => self.output.cut_selection(),
+            Action::Paste => self.output.paste(),
+            Action::ClearSelection => self.output.clear_selection(),
+            Action::KillLine => self.output.kill_line(self.previous_action == Some(Action::KillLine)),
+            Action::Yank => self.output.yank(),
+            Action::YankPop if matches!(self.previous_action, Some(Action::Yank | Action::YankPop)) => {
+                self.output.yank_pop();
+            }
+            Action::YankPop => {
+                self.output.status_message.set_message("Previous command was not a yank".to_string());
+            }
+            Action::SetMark => self.output.set_mark(),
+            Action::IsearchForward => self.output.isearch(SearchDirection::Forward)?,
+            Action::IsearchBackward => self.output.isearch(SearchDirection::Backward)?,
+            Action::SplitBelow => self.output.split_window(false),
+            Action::SplitRight => self.output.split_window(true),
+            Action::NextWindow => self.output.next_window(),
+            Action::CloseWindow => self.output.close_window(),
+            Action::OnlyWindow => self.output.only_window(),
+            Action::GrowHeight => self.output.resize_window(false, 1),
+            Action::ShrinkHeight => self.output.resize_window(false, -1),
+            Action::GrowWidth => self.output.resize_window(true, 1),
+            Action::ShrinkWidth => self.output.resize_window(true, -1),
             _ => {}
         }
         Ok(true)
@@ -173,20 +431,14 @@ impl Editor {
     }
 }
 
+// escape sequences waiting for the next flush
 pub struct EditorContents {
-    // print the `~`
     content: String,
 }
 impl EditorContents {
     fn new() -> Self {
         Self { content: String::new() }
     }
-    fn push(&mut self, ch: char) {
-        self.content.push(ch);
-    }
-    fn push_str(&mut self, string: &str) {
-        self.content.push_str(string);
-    }
 }
 
 impl io::Write for EditorContents {
@@ -211,63 +463,172 @@ impl io::Write for EditorContents {
 struct Output {
     win_size: (usize, usize),
     editor_contents: EditorContents,
+    screen: Screen, // the frame being drawn and the one on the terminal
     cursor_controller: CursorController,
     editor_rows: EditorRows,
     status_message: StatusMessage,
     dirty: u64, // dirty flag
     search_index: SearchIndex,
-    syntax_highlight: Option<Box<dyn SyntaxHighlight>>,
-}
-
-syntax_struct! {
-    struct RustHighlight {
-        extensions: &["rs"],
-        file_type: "rust",
-        comment_start: "//",
-        keywords: {
-            [Color::DarkRed;
-                "mod","unsafe","extern","crate","use","type","struct","enum","union","const","static",
-                "mut","let","if","else","impl","trait","for","fn","self","Self", "while", "true","false",
-                "in","continue","break","loop","match"
-            ],
-            [Color::DarkMagenta;
-                "isize","i8","i16","i32","i64","usize","u8","u16","u32","u64","f32","f64",
-                "char","str","bool","Fn","FnOnce","FnMut","A","B","C","D","E","F","G","H","I",
-                "J","K","L","M","N","O","P","Q","R","S","T","U","V","W","X","Y","Z"
-            ]
-        },
-        multiline_comment: Some(("/*","*/"))
-    }
+    history: History,
+    clipboard: Clipboard,
+    mode: Mode,
+    // the fields above belong to `buffers[current_buffer]`, its slot holds a placeholder
+    buffers: Vec<Buffer>,
+    current_buffer: usize,
+    // `cursor_controller` belongs to `windows[current_window]`
+    windows: Vec<Window>,
+    current_window: usize,
+    layout: Layout,
+    line_numbers: LineNumbers,
+    wrap: Wrap,
+    config: Config,
+    theme: Theme,
+    last_click: Option<(Instant, (usize, usize))>, // to tell a double click
+    drag_from: Option<Position>, // where the left button went down
+    yanked: Option<(Position, Position)>, // the text of the last yank, replaced by `M-y`
+    syntaxes: Syntaxes,
 }
 
 impl Output {
-    fn new() -> Self {
+    fn new(config: Config, syntaxes: Syntaxes, theme: Theme, help: String, errors: Vec<String>) -> Self {
         let win_size = terminal
             ::size()
-            .map(|(x, y)| (x as usize, (y as usize) - 2))
+            .map(|(x, y)| (x as usize, (y as usize).saturating_sub(1)))
             .unwrap();
-        let mut syntax_highlight = None;
+        let mut status_message = StatusMessage::new(help);
+        status_message.timeout = Duration::from_secs(config.message_timeout);
+        if !errors.is_empty() {
+            status_message.set_message(format!("Config: {}", errors.join(" | ")));
+        }
+        let mut buffers: Vec<Buffer> = Vec::new();
+        // 每个命令行参数打开一个 buffer
+        for file_path in env::args().skip(1) {
+            // the same file twice on the command line is opened once
+            if buffers.iter().any(|buffer| buffer.editor_rows.is_file(Path::new(&file_path))) {
+                continue;
+            }
+            match EditorRows::from_file(file_path.clone().into()) {
+                Ok(mut editor_rows) => {
+                    editor_rows.set_syntax(syntaxes.for_rows(&editor_rows));
+                    buffers.push(Buffer::new(editor_rows, win_size));
+                }
+                Err(err) => status_message.set_message(format!("Can't open {}: {}", file_path, err)),
+            }
+        }
+        if buffers.is_empty() {
+            buffers.push(Buffer::new(EditorRows::new(), win_size));
+        }
+        Output::from_buffers(win_size, buffers, status_message, config, syntaxes, theme)
+    }
+
+    // the first of `buffers` is shown
+    fn from_buffers(
+        win_size: (usize, usize),
+        mut buffers: Vec<Buffer>,
+        status_message: StatusMessage,
+        config: Config,
+        syntaxes: Syntaxes,
+        theme: Theme
+    ) -> Self {
+        for buffer in &mut buffers {
+            buffer.editor_rows.tab_stop = config.tab_width;
+        }
+        let buffer = mem::replace(&mut buffers[0], Buffer::new(EditorRows::new(), win_size));
         Output {
             win_size,
             editor_contents: EditorContents::new(),
-            cursor_controller: CursorController::new(win_size),
-            editor_rows: EditorRows::new(&mut syntax_highlight),
-            status_message: StatusMessage::new(
-                "HELP: Ctrl-Q = Quit | Ctrl-s = Save | Ctrl-F = Find".to_string()
-            ),
-            dirty: 0,
-            search_index: SearchIndex::new(),
-            syntax_highlight,
+            screen: Screen::new(),
+            cursor_controller: buffer.cursor_controller,
+            editor_rows: buffer.editor_rows,
+            status_message,
+            dirty: buffer.dirty,
+            search_index: buffer.search_index,
+            history: buffer.history,
+            clipboard: Clipboard::new(),
+            mode: Mode::Insert,
+            buffers,
+            current_buffer: 0,
+            windows: vec![Window { buffer: 0, cursor_controller: CursorController::new(win_size) }],
+            current_window: 0,
+            layout: Layout::Window(0),
+            line_numbers: config.line_numbers,
+            wrap: config.wrap,
+            theme,
+            config,
+            last_click: None,
+            drag_from: None,
+            yanked: None,
+            syntaxes,
+        }
+    }
+    fn window_rects(&self) -> (Vec<(usize, Rect)>, Vec<Rect>) {
+        let (mut windows, mut separators) = (Vec::new(), Vec::new());
+        let screen = Rect { x: 0, y: 0, width: self.win_size.0, height: self.win_size.1 };
+        self.layout.rects(screen, &mut windows, &mut separators);
+        (windows, separators)
+    }
+
+    // 绘制一个窗口
+    fn draw_window(&mut self, window: usize, rect: Rect) {
+        let current = window == self.current_window;
+        let buffer = self.windows[window].buffer;
+        let mut cursor_controller = if current {
+            self.cursor_controller
+        } else {
+            self.windows[window].cursor_controller
+        };
+        cursor_controller.screen_columns = rect.width;
+        cursor_controller.screen_rows = rect.height.saturating_sub(1);
+        let (line_numbers, theme) = (self.line_numbers, self.theme);
+        cursor_controller.wrap = self.wrap;
+        let (editor_rows, search_index) = if buffer == self.current_buffer {
+            (&mut self.editor_rows, &self.search_index)
+        } else {
+            let buffer = &mut self.buffers[buffer];
+            (&mut buffer.editor_rows, &buffer.search_index)
+        };
+        // another window may have shortened the buffer
+        let number_of_rows = editor_rows.number_of_rows();
+        cursor_controller.cursor_y = cmp::min(cursor_controller.cursor_y, number_of_rows);
+        if cursor_controller.cursor_y < number_of_rows {
+            let row_len = editor_rows.get_editor_row(cursor_controller.cursor_y).len();
+            cursor_controller.cursor_x = cmp::min(cursor_controller.cursor_x, row_len);
+        } else {
+            cursor_controller.cursor_x = 0;
+        }
+        cursor_controller.gutter_width = cmp::min(line_numbers.width(number_of_rows), rect.width);
+        cursor_controller.scroll(editor_rows); //窗口垂直、水平滚动
+        Output::draw_rows(&mut self.screen, editor_rows, search_index, &cursor_controller, line_numbers, &theme, rect);
+        if current {
+            self.cursor_controller = cursor_controller;
+        } else {
+            self.windows[window].cursor_controller = cursor_controller;
         }
+        self.draw_status_bar(window, rect);
     }
+
     // 绘制文件行
-    fn draw_rows(&mut self) {
-        let screen_rows = self.win_size.1;
-        let screen_columns = self.win_size.0;
+    fn draw_rows(
+        out: &mut Screen,
+        editor_rows: &mut EditorRows,
+        search_index: &SearchIndex,
+        cursor_controller: &CursorController,
+        line_numbers: LineNumbers,
+        theme: &Theme,
+        rect: Rect
+    ) {
+        let screen_rows = cursor_controller.screen_rows;
+        let screen_columns = cursor_controller.text_columns();
+        let gutter_width = cursor_controller.gutter_width;
+        let selection = Output::clamp_selection(cursor_controller.selection(), editor_rows);
+        // scroll,when cursor_controller.row_offset 发生变化时用来读取vec里的字符
+        let mut file_row = cursor_controller.row_offset;
+        let mut line = cursor_controller.wrap_offset; // screen line inside `file_row`
+        let mut current_row = None;
         for i in 0..screen_rows {
-            let file_row = i + self.cursor_controller.row_offset; // scroll,when self.cursor_controller.row_offset 发生变化时用来读取vec里的字符
-            if file_row >= self.editor_rows.number_of_rows() {
-                if i == screen_rows / 3 && 0 == self.editor_rows.number_of_rows() {
+            out.move_to(rect.x, rect.y + i);
+            if file_row >= editor_rows.number_of_rows() {
+                if i == screen_rows / 3 && 0 == editor_rows.number_of_rows() {
                     // println welcomes message.
                     let mut welcomes = format!("Pound Editor --- Version {}", VERSION);
                     if welcomes.len() > screen_columns {
@@ -276,99 +637,215 @@ impl Output {
                     // 计算padding
                     let mut padding = (screen_columns - welcomes.len()) / 2;
                     if padding != 0 {
-                        self.editor_contents.push(' ');
+                        out.push(' ');
                         padding -= 1;
                     }
-                    (0..padding).for_each(|_| self.editor_contents.push(' '));
-                    self.editor_contents.push_str(&welcomes);
-                } else {
-                    self.editor_contents.push('~');
+                    (0..padding).for_each(|_| out.push(' '));
+                    out.push_str(&welcomes);
+                } else if screen_columns > 0 {
+                    out.push('~');
                 }
             } else {
-                if i < self.editor_rows.number_of_rows() {
-                    // let len = cmp::min(self.editor_rows.get_render_row(file_row).len(), screen_columns);
-                    // self.editor_contents.push_str(&self.editor_rows.get_render_row(file_row)[..len]);
-                    let row = self.editor_rows.get_editor_row(file_row);
-                    let current_row_len = row.render.len();
-                    let column_offset = self.cursor_controller.column_offset;
-                    let len = if current_row_len < column_offset {
-                        0
+                if gutter_width > 0 && line == 0 {
+                    let label = line_numbers.label(file_row, cursor_controller.cursor_y);
+                    let label = format!("{:>width$} ", label, width = gutter_width - 1);
+                    out.set_style(theme.gutter);
+                    out.push_str(&label[label.len() - gutter_width..]);
+                    out.set_style(Style::default());
+                } else {
+                    (0..gutter_width).for_each(|_| out.push(' '));
+                }
+                let selected = Output::selected_range(editor_rows, selection, file_row);
+                let current_match = search_index.match_range
+                    .filter(|(row_idx, _, _)| *row_idx == file_row)
+                    .map(|(_, from, to)| (from, to));
+                // 只计算屏幕上的行
+                let (row, segments) = current_row.get_or_insert_with(|| {
+                    (editor_rows.get_highlighted_row(file_row), cursor_controller.segments(editor_rows, file_row))
+                });
+                let line_count = segments.len();
+                // 按显示宽度裁剪, or the screen line when wrapping
+                let (start, end, padding) = if cursor_controller.wrap == Wrap::Off {
+                    row.visible_range(cursor_controller.column_offset, screen_columns)
+                } else {
+                    (segments[line], segments.get(line + 1).copied().unwrap_or(row.render.len()), 0)
+                };
+                let len = end - start;
+                let render = &row.render[start..end];
+                (0..padding).for_each(|_| out.push(' '));
+                // bytes relative to `render`
+                let visible = |(from, to): (usize, usize)| {
+                    let from = cmp::min(from.saturating_sub(start), len);
+                    (from, cmp::max(from, cmp::min(to.saturating_sub(start), len)))
+                };
+                // matched on the text itself like `replace_matches`, a tab only matches `\t`
+                let search_matches: Vec<(usize, usize)> = search_index.regex
+                    .as_ref()
+                    .map(|regex| {
+                        regex
+                            .find_iter(&row.row_content)
+                            .map(|found| (row.render_offset(found.start()), row.render_offset(found.end())))
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                if selected.is_none() && search_matches.is_empty() && row.highlight.is_empty() {
+                    out.push_str(render);
+                } else {
+                    let mut highlight = if row.highlight.is_empty() {
+                        vec![HighlightType::Normal; len]
                     } else {
-                        let len = current_row_len - column_offset;
-                        if len > screen_columns {
-                            screen_columns
-                        } else {
-                            len
-                        }
+                        row.highlight[start..end].to_vec()
                     };
-                    let start = if len == 0 { 0 } else { column_offset };
-                    let render = &row.render[start..start + len];
-                    self.syntax_highlight
-                        .as_ref()
-                        .map(|syntax_struct| {
-                            syntax_struct.color_row(
-                                render,
-                                &row.highlight[start..start + len],
-                                &mut self.editor_contents
-                            )
-                        })
-                        .unwrap_or_else(|| self.editor_contents.push_str(render));
-                    // self.editor_contents.push_str(&row[start..start + len]);
+                    for (from, to) in search_matches.into_iter().map(visible) {
+                        highlight[from..to].fill(HighlightType::SearchMatch);
+                    }
+                    // the current match looks like a selection
+                    if let Some((from, to)) = selected.or(current_match).map(visible) {
+                        highlight[from..to].fill(HighlightType::Selection);
+                    }
+                    syntax_struct::color_row(render, &highlight, out, |highlight| theme.style(highlight));
+                }
+                line += 1;
+                if line >= line_count {
+                    file_row += 1;
+                    line = 0;
+                    current_row = None;
                 }
             }
-            queue!(self.editor_contents, terminal::Clear(ClearType::UntilNewLine)).unwrap();
-            // if i < screen_rows - 1 {
-            self.editor_contents.push_str("\r\n");
-            // }
         }
     }
     // tab bar
-    fn draw_status_bar(&mut self) {
-        self.editor_contents.push_str(&style::Attribute::Reverse.to_string());
-        let dirty = if self.dirty > 0 { "(modified)" } else { "" };
+    fn draw_status_bar(&mut self, window: usize, rect: Rect) {
+        if rect.height == 0 {
+            return;
+        }
+        let current = window == self.current_window;
+        let buffer = self.windows[window].buffer;
+        let (editor_rows, dirty) = self.buffer_state(buffer);
+        let cursor_y = if current {
+            self.cursor_controller.cursor_y
+        } else {
+            self.windows[window].cursor_controller.cursor_y
+        };
+        let dirty = if dirty > 0 { "(modified)" } else { "" };
+        let buffer_number = if self.buffers.len() > 1 {
+            format!("[{}/{}] ", buffer + 1, self.buffers.len())
+        } else {
+            String::new()
+        };
+        // only the current window shows the mode
+        let mode = if current { self.mode.indicator() } else { "" };
         let info = format!(
-            "{} {} -- {}lines",
-            self.editor_rows.filename
-                .as_ref()
-                .and_then(|path| path.file_name())
-                .and_then(|name| name.to_str())
-                .unwrap_or("[No Name]"),
+            "{} {}{} {} -- {}lines",
+            mode,
+            buffer_number,
+            Output::buffer_name(editor_rows),
             dirty,
-            self.editor_rows.number_of_rows()
+            editor_rows.number_of_rows()
         );
-        let info_len = cmp::min(info.len(), self.win_size.0);
         let line_info = format!(
-            "{} {}/{}",
-            self.syntax_highlight
+            "{} | {} {}/{}",
+            editor_rows.syntax_highlight
                 .as_ref()
                 .map(|high| high.file_type())
                 .unwrap_or("no file_type"),
-            self.cursor_controller.cursor_y,
-            self.editor_rows.number_of_rows()
+            editor_rows.format.describe(),
+            cursor_y,
+            editor_rows.number_of_rows()
         );
-        self.editor_contents.push_str(&info[..info_len]);
+        let info = editor_row::truncate_width(&info, rect.width).to_string();
+        let info_len = editor_row::display_width(&info);
+        self.screen.move_to(rect.x, rect.y + rect.height - 1);
+        self.screen.set_style(self.theme.status_bar);
+        self.screen.push_str(&info);
 
-        for idx in info_len..self.win_size.0 {
+        for idx in info_len..rect.width {
             // 计算剩余位置
-            if self.win_size.0 - idx == line_info.len() {
-                self.editor_contents.push_str(&line_info);
+            if rect.width - idx == editor_row::display_width(&line_info) {
+                self.screen.push_str(&line_info);
                 break;
             } else {
-                self.editor_contents.push(' ');
+                self.screen.push(' ');
             }
         }
-        self.editor_contents.push_str(&style::Attribute::Reset.to_string());
-        // self.editor_contents.push_str("\r\n");
+        self.screen.set_style(Style::default());
     }
 
     fn draw_message_bar(&mut self) {
-        queue!(self.editor_contents, terminal::Clear(ClearType::UntilNewLine)).unwrap();
-        if let Some(msg) = self.status_message.message() {
-            let msg_len = msg.len();
-            let min_msg_len = cmp::min(self.win_size.0, msg_len);
-            self.editor_contents.push_str(&msg[..min_msg_len]);
+        self.screen.move_to(0, self.win_size.1);
+        self.screen.set_style(self.theme.message_bar);
+        let msg = self.status_message.message().map_or("", |msg| editor_row::truncate_width(msg, self.win_size.0));
+        self.screen.push_str(msg);
+        // the background goes across the whole row
+        (editor_row::display_width(msg)..self.win_size.0).for_each(|_| self.screen.push(' '));
+        self.screen.set_style(Style::default());
+    }
+
+    // redraws right away when the terminal is resized while waiting.
+    // 没有输入的时候 the rest of the buffers gets highlighted
+    fn read_input(&mut self, reader: &Reader) -> crossterm::Result<Event> {
+        loop {
+            let pending = self.highlight_in_background();
+            if self.highlight_needs_redraw() {
+                self.refresh_screen()?;
+            }
+            let event = if pending { reader.poll_event(Duration::ZERO)? } else { Some(reader.read_event()?) };
+            match event {
+                Some(Event::Resize(columns, rows)) => {
+                    self.resize(columns as usize, rows as usize);
+                    self.refresh_screen()?;
+                }
+                Some(event) => return Ok(event),
+                None => {}
+            }
+        }
+    }
+
+    // some rows of the current buffer, then of the others. false once all are done
+    fn highlight_in_background(&mut self) -> bool {
+        if self.editor_rows.highlight_pending() {
+            return self.editor_rows.highlight_rows(BACKGROUND_ROWS) || self.buffers.iter().any(|buffer| buffer.editor_rows.highlight_pending());
+        }
+        match self.buffers.iter_mut().find(|buffer| buffer.editor_rows.highlight_pending()) {
+            Some(buffer) => {
+                buffer.editor_rows.highlight_rows(BACKGROUND_ROWS);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // rows on screen were drawn before the rows above them were highlighted
+    fn highlight_needs_redraw(&mut self) -> bool {
+        let mut redraw = self.editor_rows.needs_redraw();
+        for buffer in &mut self.buffers {
+            redraw |= buffer.editor_rows.needs_redraw();
+        }
+        redraw
+    }
+
+    // prompts ignore the mouse
+    fn read_key(&mut self, reader: &Reader) -> crossterm::Result<KeyEvent> {
+        loop {
+            if let Event::Key(key) = self.read_input(reader)? {
+                return Ok(key);
+            }
+        }
+    }
+
+    // the windows get their new size from `layout` when they are drawn
+    fn resize(&mut self, columns: usize, rows: usize) {
+        self.win_size = (columns, rows.saturating_sub(1));
+        let (windows, _) = self.window_rects();
+        for (window, rect) in windows {
+            let cursor_controller = if window == self.current_window {
+                &mut self.cursor_controller
+            } else {
+                &mut self.windows[window].cursor_controller
+            };
+            cursor_controller.screen_columns = rect.width;
+            cursor_controller.screen_rows = rect.height.saturating_sub(1);
         }
-        self.editor_contents.push_str("\r\n");
     }
 
     fn clear_screen() -> crossterm::Result<()> {
@@ -378,220 +855,980 @@ impl Output {
     }
 
     fn refresh_screen(&mut self) -> crossterm::Result<()> {
-        self.cursor_controller.scroll(&self.editor_rows); //窗口垂直、水平滚动
-        queue!(
-            self.editor_contents,
-            cursor::Hide,
-            terminal::Clear(ClearType::All),
-            cursor::MoveTo(0, 0)
-        )?;
-
-        self.draw_rows(); //like vim;
+        // the message bar takes the last row
+        self.screen.begin(self.win_size.0, self.win_size.1 + 1);
+        let (windows, separators) = self.window_rects();
+        for &(window, rect) in &windows {
+            self.draw_window(window, rect); //like vim;
+        }
+        for separator in separators {
+            for y in separator.y..separator.y + separator.height {
+                self.screen.move_to(separator.x, y);
+                self.screen.push('│');
+            }
+        }
         self.draw_message_bar();
-        self.draw_status_bar();
+        // only what changed since the last frame
+        queue!(self.editor_contents, cursor::Hide)?;
+        self.screen.render(&mut self.editor_contents)?;
 
-        let (cursor_x, cursor_y) = (
-            // saturating at the numeric bounds instead of overflowing.
-            self.cursor_controller.render_x.saturating_sub(self.cursor_controller.column_offset),
-            self.cursor_controller.cursor_y.saturating_sub(self.cursor_controller.row_offset),
-        );
-        queue!(
-            self.editor_contents,
-            cursor::MoveTo(cursor_x as u16, cursor_y as u16),
-            cursor::Show
-        )?;
+        let rect = windows
+            .iter()
+            .find(|(window, _)| *window == self.current_window)
+            .map_or(Rect { x: 0, y: 0, width: 0, height: 0 }, |(_, rect)| *rect);
+        // hidden while the wheel has scrolled it out of the window
+        if let Some(screen_y) = self.cursor_controller.screen_y {
+            let (cursor_x, cursor_y) = (
+                // saturating at the numeric bounds instead of overflowing.
+                rect.x +
+                    self.cursor_controller.gutter_width +
+                    self.cursor_controller.render_x.saturating_sub(self.cursor_controller.column_offset),
+                rect.y + screen_y,
+            );
+            queue!(
+                self.editor_contents,
+                cursor::MoveTo(cursor_x as u16, cursor_y as u16),
+                cursor::Show
+            )?;
+        }
         // 标准输出
         self.editor_contents.flush()
     }
 
+    // the window under the screen cell (column, row)
+    fn window_at(&self, column: usize, row: usize) -> Option<(usize, Rect)> {
+        self.window_rects().0
+            .into_iter()
+            .find(|(_, rect)| {
+                (rect.x..rect.x + rect.width).contains(&column) && (rect.y..rect.y + rect.height).contains(&row)
+            })
+    }
+
+    // the buffer position shown at the screen cell (column, row) of `window`
+    fn position_at(&self, window: usize, rect: Rect, column: usize, row: usize) -> Position {
+        let cursor_controller = if window == self.current_window {
+            &self.cursor_controller
+        } else {
+            &self.windows[window].cursor_controller
+        };
+        let editor_rows = self.buffer_state(self.windows[window].buffer).0;
+        let number_of_rows = editor_rows.number_of_rows();
+        let (mut y, mut line) = (cursor_controller.row_offset, cursor_controller.wrap_offset);
+        let lines = cmp::min(row.saturating_sub(rect.y), cursor_controller.screen_rows.saturating_sub(1));
+        for _ in 0..lines {
+            if y >= number_of_rows {
+                break;
+            }
+            if line + 1 < cursor_controller.segments(editor_rows, y).len() {
+                line += 1;
+            } else {
+                y += 1;
+                line = 0;
+            }
+        }
+        // below the text
+        if y >= number_of_rows {
+            return match number_of_rows {
+                0 => (0, 0),
+                _ => (editor_rows.get_editor_row(number_of_rows - 1).len(), number_of_rows - 1),
+            };
+        }
+        let column = column.saturating_sub(rect.x + cursor_controller.gutter_width);
+        let editor_row = editor_rows.get_editor_row(y);
+        let segments = cursor_controller.segments(editor_rows, y);
+        let render_x = if cursor_controller.wrap == Wrap::Off {
+            cursor_controller.column_offset + column
+        } else {
+            editor_row.render_column(segments[line]) + column
+        };
+        let mut x = editor_row.get_row_content_x(render_x);
+        // stay on the clicked screen line
+        if let Some(&end) = segments.get(line + 1) {
+            while x > 0 && editor_row.render_index(x) >= end {
+                x -= 1;
+            }
+        }
+        (x, y)
+    }
+
+    // left button, a second click on the same cell selects the word under it
+    fn click(&mut self, column: usize, row: usize) {
+        let (window, rect) = match self.window_at(column, row) {
+            Some(found) => found,
+            None => return,
+        };
+        self.focus_window(window);
+        self.leave_view();
+        self.cursor_controller.free_scroll = false;
+        let position = self.position_at(window, rect, column, row);
+        self.set_cursor_position(position);
+        let double_click = self.last_click
+            .is_some_and(|(time, cell)| cell == (column, row) && time.elapsed() < DOUBLE_CLICK);
+        if double_click {
+            self.last_click = None;
+            self.drag_from = None;
+            self.select_word(position);
+        } else {
+            self.last_click = Some((Instant::now(), (column, row)));
+            self.drag_from = Some(position);
+        }
+    }
+
+    fn select_word(&mut self, at: Position) {
+        let (start, end) = vim::word_at(&self.editor_rows, at);
+        if start == end {
+            return;
+        }
+        self.cursor_controller.anchor = Some(start);
+        if self.mode == Mode::Insert {
+            self.set_cursor_position(end);
+        } else {
+            // the Visual selection includes the character under the cursor
+            self.mode = Mode::Visual;
+            self.set_cursor_position((end.0 - 1, end.1));
+        }
+    }
+
+    // extends the selection from where the left button went down
+    fn drag(&mut self, column: usize, row: usize) {
+        let from = match self.drag_from {
+            Some(from) => from,
+            None => return,
+        };
+        let rect = match self.window_rects().0.into_iter().find(|(window, _)| *window == self.current_window) {
+            Some((_, rect)) => rect,
+            None => return,
+        };
+        let position = self.position_at(self.current_window, rect, column, row);
+        if self.cursor_controller.anchor.is_none() {
+            self.cursor_controller.anchor = Some(from);
+            if self.mode == Mode::Normal {
+                self.mode = Mode::Visual;
+            }
+        }
+        self.cursor_controller.free_scroll = false;
+        self.set_cursor_position(position);
+    }
+
+    // the wheel scrolls the window under the pointer, the cursor stays
+    fn scroll_window(&mut self, column: usize, row: usize, lines: isize) {
+        let window = match self.window_at(column, row) {
+            Some((window, _)) => window,
+            None => return,
+        };
+        let buffer = self.windows[window].buffer;
+        let editor_rows = if buffer == self.current_buffer {
+            &self.editor_rows
+        } else {
+            &self.buffers[buffer].editor_rows
+        };
+        let cursor_controller = if window == self.current_window {
+            &mut self.cursor_controller
+        } else {
+            &mut self.windows[window].cursor_controller
+        };
+        cursor_controller.scroll_by(lines, editor_rows);
+    }
+
     fn move_cursor(&mut self, direction: KeyCode) {
+        self.history.break_group();
         self.cursor_controller.move_cursor(direction, &self.editor_rows);
     }
 
-    fn inset_char(&mut self, ch: char) {
-        if self.cursor_controller.cursor_y == self.editor_rows.number_of_rows() {
-            self.editor_rows.insert_row(self.editor_rows.number_of_rows(), String::new());
-            self.dirty += 1;
+    // like vim `w` / `b`
+    fn move_word(&mut self, forward: bool) {
+        self.history.break_group();
+        let position = self.cursor_position();
+        if position.1 >= self.editor_rows.number_of_rows() {
+            if !forward {
+                self.move_cursor(KeyCode::Left);
+            }
+            return;
+        }
+        let position = if forward {
+            vim::next_word_start(&self.editor_rows, position)
+        } else {
+            vim::previous_word_start(&self.editor_rows, position)
+        };
+        self.set_cursor_position(position);
+    }
+
+    fn move_page(&mut self, direction: KeyCode) {
+        if matches!(direction, KeyCode::PageUp) {
+            self.cursor_controller.cursor_y = self.cursor_controller.row_offset;
+        } else {
+            self.cursor_controller.cursor_y = cmp::min(
+                self.cursor_controller.screen_rows + self.cursor_controller.row_offset - 1,
+                self.editor_rows.number_of_rows()
+            );
+        }
+        let key = if matches!(direction, KeyCode::PageUp) { KeyCode::Up } else { KeyCode::Down };
+        (0..self.cursor_controller.screen_rows).for_each(|_| {
+            self.move_cursor(key);
+        });
+    }
+
+    fn cursor_position(&self) -> Position {
+        (self.cursor_controller.cursor_x, self.cursor_controller.cursor_y)
+    }
+
+    fn set_cursor_position(&mut self, (x, y): Position) {
+        self.cursor_controller.cursor_x = x;
+        self.cursor_controller.cursor_y = y;
+    }
+
+    // every edit goes through `insert_text` / `delete_text` so that it can be undone
+    fn insert_text(&mut self, at: Position, text: &str) {
+        let number_of_rows = self.editor_rows.number_of_rows();
+        let (at, text) = if at.1 == number_of_rows && at.1 > 0 {
+            // typing below the last row appends a new row first
+            let last_row_len = self.editor_rows.get_editor_row(at.1 - 1).len();
+            ((last_row_len, at.1 - 1), format!("\n{}", text))
         } else {
-            self.editor_rows
-                .get_editor_row_mut(self.cursor_controller.cursor_y)
-                .insert_char(self.cursor_controller.cursor_x, ch);
-            if let Some(it) = self.syntax_highlight.as_ref() {
-                it.update_syntax(
-                    self.cursor_controller.cursor_y,
-                    &mut self.editor_rows.row_contents
-                );
+            (at, text.to_string())
+        };
+        let cursor_before = self.cursor_position();
+        let empty_before = number_of_rows == 0;
+        let end = self.editor_rows.insert_text(at, &text);
+        self.set_cursor_position(end);
+        if !text.is_empty() {
+            let start = self.editor_rows.char_index(end) - text.chars().count();
+            self.history.record(EditOp::Insert { at: start, text }, cursor_before, end, empty_before);
+        }
+        self.update_dirty();
+    }
+
+    fn delete_text(&mut self, from: Position, to: Position) {
+        let cursor_before = self.cursor_position();
+        let empty_before = self.editor_rows.number_of_rows() == 0;
+        let start = self.editor_rows.char_index(from);
+        let text = self.editor_rows.delete_text(from, to);
+        self.set_cursor_position(from);
+        self.history.record(EditOp::Delete { at: start, text }, cursor_before, from, empty_before);
+        self.update_dirty();
+    }
+
+    fn start_selection(&mut self) {
+        if self.cursor_controller.anchor.is_none() {
+            self.cursor_controller.anchor = Some(self.cursor_position());
+            self.cursor_controller.mark_active = false;
+        }
+    }
+
+    // shift + movement, or any movement after emacs `C-Space`, extends the selection
+    fn extend_selection(&mut self, select: bool) {
+        let mark = self.cursor_controller.mark_active && self.cursor_controller.anchor.is_some();
+        if select || mark {
+            self.start_selection();
+        } else {
+            self.cursor_controller.anchor = None;
+        }
+    }
+
+    fn clear_selection(&mut self) {
+        self.cursor_controller.anchor = None;
+        self.cursor_controller.mark_active = false;
+    }
+
+    // emacs `C-Space`
+    fn set_mark(&mut self) {
+        self.cursor_controller.anchor = Some(self.cursor_position());
+        self.cursor_controller.mark_active = true;
+        self.status_message.set_message("Mark set".to_string());
+    }
+
+    // the selection, clamped to existing rows
+    fn selection(&self) -> Option<(Position, Position)> {
+        Output::clamp_selection(self.cursor_controller.selection(), &self.editor_rows)
+    }
+
+    fn clamp_selection(
+        selection: Option<(Position, Position)>,
+        editor_rows: &EditorRows
+    ) -> Option<(Position, Position)> {
+        let (from, to) = selection?;
+        let number_of_rows = editor_rows.number_of_rows();
+        if from == to || from.1 >= number_of_rows {
+            return None;
+        }
+        if to.1 >= number_of_rows {
+            let last_row = number_of_rows - 1;
+            return Some((from, (editor_rows.get_editor_row(last_row).len(), last_row)));
+        }
+        Some((from, to))
+    }
+
+    // selected bytes of the render of row `at`
+    fn selected_range(
+        editor_rows: &EditorRows,
+        selection: Option<(Position, Position)>,
+        at: usize
+    ) -> Option<(usize, usize)> {
+        let (from, to) = selection?;
+        if at < from.1 || at > to.1 {
+            return None;
+        }
+        let row = editor_rows.get_editor_row(at);
+        let start = if at == from.1 { row.render_index(from.0) } else { 0 };
+        let end = if at == to.1 { row.render_index(to.0) } else { row.render.len() };
+        Some((start, end))
+    }
+
+    fn copy_selection(&mut self) -> bool {
+        match self.selection() {
+            Some((from, to)) => {
+                let text = self.editor_rows.get_text(from, to);
+                self.status_message.set_message(format!("{} bytes copied.", text.len()));
+                self.clipboard.set(text, false);
+                true
+            }
+            None => {
+                self.status_message.set_message("Nothing selected".to_string());
+                false
             }
-            self.cursor_controller.cursor_x += 1;
-            self.dirty += 1;
         }
     }
 
-    fn delete_char(&mut self) {
-        if self.cursor_controller.cursor_y == self.editor_rows.number_of_rows() {
+    fn delete_selection(&mut self) -> bool {
+        let selection = self.selection();
+        self.clear_selection();
+        match selection {
+            Some((from, to)) => {
+                self.delete_text(from, to);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn cut_selection(&mut self) {
+        if self.copy_selection() {
+            self.delete_selection();
+        }
+    }
+
+    // typed text replaces the selection
+    fn replace_selection(&mut self, text: &str) {
+        // a group would close the open transaction, typing has to merge into it
+        if self.selection().is_none() {
+            self.insert_text(self.cursor_position(), text);
             return;
         }
-        if self.cursor_controller.cursor_y == 0 && self.cursor_controller.cursor_x == 0 {
+        self.history.begin_group();
+        self.delete_selection();
+        self.insert_text(self.cursor_position(), text);
+        self.history.end_group();
+    }
+
+    fn paste(&mut self) {
+        match self.clipboard.text.clone() {
+            Some(text) => self.replace_selection(&text),
+            None => self.status_message.set_message("Clipboard is empty".to_string()),
+        }
+    }
+
+    // emacs `C-k`: the rest of the row, or the line break when the cursor is at its end
+    fn kill_line(&mut self, append: bool) {
+        let (x, y) = self.cursor_position();
+        let number_of_rows = self.editor_rows.number_of_rows();
+        if y >= number_of_rows {
             return;
         }
-        let row = self.editor_rows.get_editor_row_mut(self.cursor_controller.cursor_y);
-        if self.cursor_controller.cursor_x > 0 {
-            row.delete_char(self.cursor_controller.cursor_x - 1);
-            self.cursor_controller.cursor_x -= 1;
+        let len = self.editor_rows.get_editor_row(y).len();
+        let to = if x < len {
+            (len, y)
+        } else if y + 1 < number_of_rows {
+            (0, y + 1)
         } else {
-            let previous_row_content = self.editor_rows.get_editor_row(
-                self.cursor_controller.cursor_y - 1
-            );
-            self.cursor_controller.cursor_x = previous_row_content.row_content.len();
-            self.editor_rows.join_adjacent_rows(self.cursor_controller.cursor_y);
-            self.cursor_controller.cursor_y -= 1;
+            self.status_message.set_message("End of buffer".to_string());
+            return;
+        };
+        self.clear_selection();
+        let text = self.editor_rows.get_text((x, y), to);
+        if append {
+            self.clipboard.append(&text);
+        } else {
+            self.clipboard.set(text, false);
         }
-        if let Some(it) = self.syntax_highlight.as_ref() {
-            it.update_syntax(self.cursor_controller.cursor_y, &mut self.editor_rows.row_contents);
+        self.history.break_group();
+        self.delete_text((x, y), to);
+    }
+
+    // emacs `C-y`: the last kill goes in at the cursor
+    fn yank(&mut self) {
+        self.clear_selection();
+        let text = match self.clipboard.yank() {
+            Some(text) => text.to_string(),
+            None => {
+                self.status_message.set_message("Kill ring is empty".to_string());
+                return;
+            }
+        };
+        self.history.break_group();
+        let from = self.cursor_position();
+        self.insert_text(from, &text);
+        self.yanked = Some((from, self.cursor_position()));
+    }
+
+    // emacs `M-y` right after a yank: the yanked text becomes the kill before it
+    fn yank_pop(&mut self) {
+        let (from, to) = match self.yanked {
+            Some(yanked) => yanked,
+            None => return,
+        };
+        let text = match self.clipboard.rotate() {
+            Some(text) => text.to_string(),
+            None => return,
+        };
+        self.history.begin_group();
+        self.delete_text(from, to);
+        self.insert_text(from, &text);
+        self.history.end_group();
+        self.yanked = Some((from, self.cursor_position()));
+    }
+
+    fn inset_char(&mut self, ch: char) {
+        self.replace_selection(&ch.to_string());
+    }
+
+    // with soft tabs, spaces up to the next tab stop
+    fn insert_tab(&mut self) {
+        if !self.config.soft_tabs {
+            self.inset_char('\t');
+            return;
         }
-        self.dirty += 1;
+        let (x, y) = self.cursor_position();
+        let render_x = if y < self.editor_rows.number_of_rows() {
+            self.editor_rows.get_editor_row(y).get_render_x(x)
+        } else {
+            0
+        };
+        let spaces = self.config.tab_width - render_x % self.config.tab_width;
+        self.replace_selection(&" ".repeat(spaces));
     }
 
-    fn insert_newline(&mut self) {
-        let current_cursor_x = self.cursor_controller.cursor_x;
-        let current_cursor_y = self.cursor_controller.cursor_y;
+    fn delete_char(&mut self) {
+        let (x, y) = self.cursor_position();
+        if y == self.editor_rows.number_of_rows() || (x == 0 && y == 0) {
+            return;
+        }
+        let from = if x > 0 {
+            (x - 1, y)
+        } else {
+            (self.editor_rows.get_editor_row(y - 1).len(), y - 1)
+        };
+        self.delete_text(from, (x, y));
+    }
 
-        if current_cursor_x == 0 {
-            self.editor_rows.insert_row(current_cursor_y, String::new());
+    fn insert_newline(&mut self) {
+        // below the last row only the missing row is added
+        let text = if
+            self.cursor_controller.cursor_y == self.editor_rows.number_of_rows() &&
+            self.selection().is_none()
+        {
+            ""
         } else {
-            let current_row = self.editor_rows.get_editor_row_mut(current_cursor_y);
-            let new_row_content = current_row.row_content[current_cursor_x..].to_string();
-            current_row.row_content.truncate(current_cursor_x);
-            EditorRows::render_row(current_row);
-            self.editor_rows.insert_row(current_cursor_y + 1, new_row_content);
+            "\n"
+        };
+        self.replace_selection(text);
+    }
+
+    fn apply_op(&mut self, op: &EditOp, undo: bool) {
+        match (op, undo) {
+            (EditOp::Insert { at, text }, false) | (EditOp::Delete { at, text }, true) => {
+                self.editor_rows.insert_chars(*at, text);
+            }
+            (EditOp::Insert { at, text }, true) | (EditOp::Delete { at, text }, false) => {
+                self.editor_rows.delete_chars(*at..*at + text.chars().count());
+            }
+        }
+    }
 
-            if let Some(it) = self.syntax_highlight.as_ref() {
-                it.update_syntax(
-                    self.cursor_controller.cursor_y + 1,
-                    &mut self.editor_rows.row_contents
-                );
+    fn undo(&mut self) {
+        let (ops, cursor, empty_before) = match self.history.undo() {
+            Some(transaction) => (transaction.ops.clone(), transaction.cursor_before, transaction.empty_before),
+            None => {
+                self.status_message.set_message("Already at oldest change".to_string());
+                return;
             }
+        };
+        ops.iter().rev().for_each(|op| self.apply_op(op, true));
+        if empty_before {
+            self.editor_rows.clear_if_empty();
         }
-        self.cursor_controller.cursor_x = 0;
-        self.cursor_controller.cursor_y += 1;
-        self.dirty += 1;
+        self.cursor_controller.anchor = None;
+        self.set_cursor_position(cursor);
+        self.update_dirty();
     }
 
-    fn find_callback(output: &mut Output, keyword: &str, key_code: KeyCode) {
-        match key_code {
-            KeyCode::Esc | KeyCode::Enter {} => {
+    fn redo(&mut self) {
+        let (ops, cursor) = match self.history.redo() {
+            Some(transaction) => (transaction.ops.clone(), transaction.cursor_after),
+            None => {
+                self.status_message.set_message("Already at newest change".to_string());
+                return;
+            }
+        };
+        ops.iter().for_each(|op| self.apply_op(op, false));
+        self.cursor_controller.anchor = None;
+        self.set_cursor_position(cursor);
+        self.update_dirty();
+    }
+
+    fn find_callback(output: &mut Output, keyword: &str, key: KeyEvent) {
+        match key.code {
+            KeyCode::Esc | KeyCode::Enter => {
                 output.search_index.reset();
             }
             _ => {
-                output.search_index.y_direction = None;
-                output.search_index.x_direction = None;
-                if let Some((idx, highlight)) = output.search_index.previous_highlight.take() {
-                    output.editor_rows.get_editor_row_mut(idx).highlight = highlight;
-                }
-                match key_code {
-                    KeyCode::Up => {
-                        output.search_index.y_direction = Some(SearchDirection::Backward);
-                    }
-                    KeyCode::Down => {
-                        output.search_index.y_direction = Some(SearchDirection::Forward);
+                let direction = match key.code {
+                    KeyCode::Up | KeyCode::Left => Some(SearchDirection::Backward),
+                    KeyCode::Down | KeyCode::Right => Some(SearchDirection::Forward),
+                    // emacs, repeating `C-s` / `C-r`
+                    KeyCode::Char('s') if key.modifiers == event::KeyModifiers::CONTROL => Some(SearchDirection::Forward),
+                    KeyCode::Char('r') if key.modifiers == event::KeyModifiers::CONTROL => Some(SearchDirection::Backward),
+                    _ => None,
+                };
+                match (key.code, key.modifiers) {
+                    // toggles search again from the top
+                    (KeyCode::Char('c'), event::KeyModifiers::ALT) => {
+                        output.search_index.ignore_case = !output.search_index.ignore_case;
                     }
-                    KeyCode::Left => {
-                        output.search_index.x_direction = Some(SearchDirection::Backward);
-                    }
-                    KeyCode::Right => {
-                        output.search_index.x_direction = Some(SearchDirection::Forward);
+                    (KeyCode::Char('w'), event::KeyModifiers::ALT) => {
+                        output.search_index.whole_word = !output.search_index.whole_word;
                     }
                     _ => {}
                 }
-                for i in 0..output.editor_rows.number_of_rows() {
-                    let row_idx = match output.search_index.y_direction {
-                        Some(ref dir) => {
-                            if matches!(dir, SearchDirection::Forward) {
-                                output.search_index.y_index += i + 1;
-                                output.search_index.y_index
-                            } else {
-                                let back = output.search_index.y_index.saturating_sub(i);
-                                if back == 0 {
-                                    break;
-                                }
-                                back - 1
-                            }
-                        }
-                        None => {
-                            if output.search_index.x_direction.is_none() {
-                                output.search_index.y_index = i;
-                            }
-                            output.search_index.y_index
-                        }
-                    };
-                    if row_idx > output.editor_rows.number_of_rows() - 1 {
-                        // output.search_index.reset();
-                        break;
-                    }
-                    let row = output.editor_rows.get_editor_row_mut(row_idx);
-                    let idx = match output.search_index.x_direction {
-                        Some(ref dir) => {
-                            let idx = if matches!(dir, SearchDirection::Forward) {
-                                let start = cmp::min(
-                                    row.render.len(),
-                                    output.search_index.x_index + 1
-                                );
-
-                                row.render[start..].find(keyword).map(|idx| idx + start)
-                            } else {
-                                row.render[..output.search_index.x_index].rfind(keyword)
-                            };
-                            if idx.is_none() {
-                                break;
-                            }
-                            idx
-                        }
-                        None => { row.render.find(keyword) }
-                    };
-                    if let Some(idx) = idx {
-                        output.search_index.previous_highlight = Some((
-                            row_idx,
-                            row.highlight.clone(),
-                        ));
-                        // 给keyword添加高亮
-                        (idx..idx + keyword.len()).for_each(|i| {
-                            row.highlight[i] = HighlightType::SearchMatch;
-                        });
-                        output.cursor_controller.cursor_x =
-                            row.get_row_content_x(idx) + keyword.len();
-                        output.cursor_controller.cursor_y = row_idx;
-                        output.search_index.y_index = row_idx;
-                        output.search_index.x_index = idx;
-                        output.cursor_controller.row_offset = output.editor_rows.number_of_rows();
-                        break;
+                let regex = match output.search_index.regex(keyword) {
+                    Some(regex) if !keyword.is_empty() => regex,
+                    regex => {
+                        output.search_index.reset();
+                        output.search_index.invalid = regex.is_none();
+                        return;
                     }
+                };
+                let matches = output.search_matches(&regex);
+                let by_row = matches!(key.code, KeyCode::Up | KeyCode::Down);
+                let found = output.search_index.step(&matches, direction, by_row);
+                output.search_index.regex = Some(regex);
+                output.search_index.invalid = false;
+                output.search_index.count = Some((found.map_or(0, |idx| idx + 1), matches.len()));
+                output.search_index.match_range = found.map(|idx| matches[idx]);
+                if let Some((row_idx, _, end)) = output.search_index.match_range {
+                    let row = output.editor_rows.get_editor_row(row_idx);
+                    output.cursor_controller.cursor_x = row.get_row_content_x(row.render_column(end));
+                    output.cursor_controller.cursor_y = row_idx;
+                    output.cursor_controller.row_offset = output.editor_rows.number_of_rows();
                 }
             }
         }
     }
+    // every match in the file, in order
+    fn search_matches(&self, regex: &Regex) -> Vec<Match> {
+        (0..self.editor_rows.number_of_rows())
+            .flat_map(|row_idx| {
+                let row = self.editor_rows.get_editor_row(row_idx);
+                regex
+                    .find_iter(&row.row_content)
+                    .map(|found| (row_idx, row.render_offset(found.start()), row.render_offset(found.end())))
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
     fn find(&mut self) -> io::Result<()> {
         // restore cursor position
         let cursor_controller = self.cursor_controller;
+        self.history.break_group();
         let prompt = prompt!(
             self,
-            "Search: {} (ESC / Arrows / Enter)",
-            callback = Output::find_callback
+            "Search: {} (ESC / Arrows / Enter / Alt-C case / Alt-W word)",
+            callback = Output::find_callback,
+            options = |output: &Output| output.search_index.options()
         );
         if prompt.is_none() {
             self.cursor_controller = cursor_controller;
         }
         Ok(())
     }
-    fn select_syntax(extension: &str) -> Option<Box<dyn SyntaxHighlight>> {
-        let  extension_list: Vec<Box<(dyn SyntaxHighlight)>> = vec![Box::new(RustHighlight::new())];
-        extension_list.into_iter().find(|it| it.extensions().contains(&extension))
+    // emacs `C-s` / `C-r`: `find` from the cursor on, the same keys go on to the next / previous match
+    fn isearch(&mut self, direction: SearchDirection) -> io::Result<()> {
+        let (x, y) = self.cursor_position();
+        let origin = if y < self.editor_rows.number_of_rows() {
+            (y, self.editor_rows.get_editor_row(y).render_index(x))
+        } else {
+            (y, 0)
+        };
+        self.clear_selection();
+        self.search_index.origin = Some((direction, origin));
+        let result = self.find();
+        self.search_index.origin = None;
+        result
+    }
+    fn replace(&mut self) -> io::Result<()> {
+        let cursor_controller = self.cursor_controller;
+        self.history.break_group();
+        let keyword = prompt!(
+            self,
+            "Replace: {} (ESC / Enter / Alt-C case / Alt-W word)",
+            callback = Output::find_callback,
+            options = |output: &Output| output.search_index.options()
+        );
+        self.cursor_controller = cursor_controller;
+        let keyword = match keyword {
+            Some(keyword) => keyword,
+            None => {
+                self.status_message.set_message("Replace Aborted".to_string());
+                return Ok(());
+            }
+        };
+        let regex = match self.search_index.regex(&keyword) {
+            Some(regex) => regex,
+            None => {
+                self.status_message.set_message(format!("Invalid pattern: {}", keyword));
+                return Ok(());
+            }
+        };
+        // `$1` / `${name}` refer to capture groups
+        let replacement = match prompt!(
+            self,
+            "Replace with: {} (ESC to cancel)",
+            callback = |&_, _, _| {},
+            options = |_: &Output| String::new(),
+            empty = true
+        ) {
+            Some(replacement) => replacement,
+            None => {
+                self.status_message.set_message("Replace Aborted".to_string());
+                return Ok(());
+            }
+        };
+        self.cursor_controller.anchor = None;
+        self.history.begin_group();
+        let result = self.replace_matches(&regex, &replacement);
+        self.history.end_group();
+        self.search_index.match_range = None;
+        let count = result?;
+        self.status_message.set_message(format!("Replaced {} occurrence(s)", count));
+        Ok(())
+    }
+    // steps through the matches, y/n/a/q for each one
+    fn replace_matches(&mut self, regex: &Regex, replacement: &str) -> io::Result<usize> {
+        let mut count = 0;
+        let mut replace_all = false;
+        let (mut start, mut y) = (0, 0);
+        while y < self.editor_rows.number_of_rows() {
+            let row = self.editor_rows.get_editor_row(y);
+            let captures = match row.row_content.get(start..).and_then(|_| regex.captures_at(&row.row_content, start)) {
+                Some(captures) => captures,
+                None => {
+                    start = 0;
+                    y += 1;
+                    continue;
+                }
+            };
+            let found = captures.get(0).unwrap();
+            let from = (row.row_content[..found.start()].graphemes(true).count(), y);
+            let to = (row.row_content[..found.end()].graphemes(true).count(), y);
+            let replace = if replace_all {
+                true
+            } else {
+                self.set_cursor_position(from);
+                self.search_index.match_range = Some((y, row.render_index(from.0), row.render_index(to.0)));
+                self.status_message.set_message("Replace this match? (y/n/a/q)".to_string());
+                self.refresh_screen()?;
+                match self.read_key(&Reader)?.code {
+                    KeyCode::Char('y') => true,
+                    KeyCode::Char('n') => false,
+                    KeyCode::Char('a') => {
+                        replace_all = true;
+                        true
+                    }
+                    KeyCode::Char('q') | KeyCode::Esc => break,
+                    _ => continue,
+                }
+            };
+            let mut next = found.end();
+            if replace {
+                let mut text = String::new();
+                captures.expand(replacement, &mut text);
+                self.delete_text(from, to);
+                self.insert_text(from, &text);
+                count += 1;
+                let (x, end_y) = self.cursor_position();
+                y = end_y;
+                next = self.editor_rows.get_editor_row(y).byte_index(x);
+            }
+            // an empty match moves on by one character
+            if found.start() == found.end() {
+                let row_content = self.editor_rows.get_editor_row(y).row_content;
+                next += row_content[next..].chars().next().map_or(1, char::len_utf8);
+            }
+            start = next;
+        }
+        Ok(count)
+    }
+    fn buffer_name(editor_rows: &EditorRows) -> &str {
+        editor_rows.filename
+            .as_ref()
+            .and_then(|path| path.file_name())
+            .and_then(|name| name.to_str())
+            .unwrap_or("[No Name]")
+    }
+
+    // the text and the file format are both as on disk
+    fn is_saved(&self) -> bool {
+        self.history.is_saved() && self.editor_rows.format_saved()
+    }
+
+    // 修改标记: back at the saved state of the history and the file format is clean again
+    fn update_dirty(&mut self) {
+        self.dirty = if self.is_saved() { 0 } else { self.dirty + 1 };
+    }
+
+    // (rows, dirty) of buffer `idx`, the current one lives in `self`
+    fn buffer_state(&self, idx: usize) -> (&EditorRows, u64) {
+        if idx == self.current_buffer {
+            (&self.editor_rows, self.dirty)
+        } else {
+            (&self.buffers[idx].editor_rows, self.buffers[idx].dirty)
+        }
+    }
+
+    fn swap_buffer(&mut self, idx: usize) {
+        let buffer = &mut self.buffers[idx];
+        mem::swap(&mut self.editor_rows, &mut buffer.editor_rows);
+        mem::swap(&mut self.cursor_controller, &mut buffer.cursor_controller);
+        mem::swap(&mut self.dirty, &mut buffer.dirty);
+        mem::swap(&mut self.history, &mut buffer.history);
+        mem::swap(&mut self.search_index, &mut buffer.search_index);
+    }
+
+    fn load_buffer(&mut self, idx: usize) {
+        // 当前 buffer 放回它的位置, 再取出 `idx`
+        self.swap_buffer(self.current_buffer);
+        self.swap_buffer(idx);
+        self.current_buffer = idx;
+    }
+
+    // shows buffer `idx` in the current window
+    fn switch_buffer(&mut self, idx: usize) {
+        if idx == self.current_buffer {
+            return;
+        }
+        self.load_buffer(idx);
+        self.windows[self.current_window].buffer = idx;
+        self.leave_view();
+    }
+
+    fn leave_view(&mut self) {
+        if self.mode == Mode::Visual {
+            self.mode = Mode::Normal;
+        }
+        self.clear_selection();
+        self.history.break_group();
+    }
+
+    fn focus_window(&mut self, idx: usize) {
+        if idx == self.current_window {
+            return;
+        }
+        self.windows[self.current_window].cursor_controller = self.cursor_controller;
+        if self.windows[idx].buffer != self.current_buffer {
+            self.load_buffer(self.windows[idx].buffer);
+        }
+        self.current_window = idx;
+        self.cursor_controller = self.windows[idx].cursor_controller;
+        self.leave_view();
+    }
+
+    // the new window shows the same place and gets the focus
+    fn split_window(&mut self, vertical: bool) {
+        self.windows.push(Window { buffer: self.current_buffer, cursor_controller: self.cursor_controller });
+        let new_window = self.windows.len() - 1;
+        self.layout.split(self.current_window, new_window, vertical);
+        self.focus_window(new_window);
+    }
+
+    fn close_window(&mut self) {
+        if self.windows.len() == 1 {
+            self.status_message.set_message("Can't close the last window".to_string());
+            return;
+        }
+        let closed = self.current_window;
+        self.focus_window(if closed + 1 < self.windows.len() { closed + 1 } else { closed - 1 });
+        self.layout.remove(closed);
+        self.windows.remove(closed);
+        if self.current_window > closed {
+            self.current_window -= 1;
+        }
+    }
+
+    // closes every other window
+    fn only_window(&mut self) {
+        let window = self.windows.swap_remove(self.current_window);
+        self.windows = vec![window];
+        self.current_window = 0;
+        self.layout = Layout::Window(0);
+    }
+
+    fn next_window(&mut self) {
+        self.focus_window((self.current_window + 1) % self.windows.len());
+    }
+
+    fn resize_window(&mut self, vertical: bool, delta: isize) {
+        let screen = Rect { x: 0, y: 0, width: self.win_size.0, height: self.win_size.1 };
+        if !self.layout.resize(self.current_window, vertical, delta, screen) {
+            self.status_message.set_message("No split to resize".to_string());
+        }
+    }
+
+    fn next_buffer(&mut self) {
+        self.switch_buffer((self.current_buffer + 1) % self.buffers.len());
+    }
+
+    // names of the buffers with unsaved changes
+    fn unsaved_buffers(&self) -> Vec<&str> {
+        (0..self.buffers.len())
+            .map(|idx| self.buffer_state(idx))
+            .filter(|(_, dirty)| *dirty > 0)
+            .map(|(editor_rows, _)| Output::buffer_name(editor_rows))
+            .collect()
+    }
+
+    fn open_file(&mut self) -> io::Result<()> {
+        let file_path: PathBuf = match prompt!(self, "Open: {} (ESC to cancel)") {
+            Some(file_path) => file_path.into(),
+            None => {
+                self.status_message.set_message("Open Aborted".to_string());
+                return Ok(());
+            }
+        };
+        let opened = (0..self.buffers.len()).find(|&idx| self.buffer_state(idx).0.is_file(&file_path));
+        if let Some(idx) = opened {
+            self.switch_buffer(idx);
+            return Ok(());
+        }
+        match EditorRows::from_file(file_path.clone()) {
+            Ok(mut editor_rows) => {
+                editor_rows.tab_stop = self.config.tab_width;
+                editor_rows.set_syntax(self.syntaxes.for_rows(&editor_rows));
+                self.buffers.push(Buffer::new(editor_rows, self.win_size));
+                self.switch_buffer(self.buffers.len() - 1);
+            }
+            Err(err) => {
+                self.status_message.set_message(format!("Can't open {}: {}", file_path.display(), err));
+            }
+        }
+        Ok(())
+    }
+
+    // Up / Down or the buffer number, Enter switches
+    fn pick_buffer(&mut self) -> io::Result<()> {
+        let mut selected = self.current_buffer;
+        loop {
+            let list = (0..self.buffers.len())
+                .map(|idx| {
+                    let (editor_rows, dirty) = self.buffer_state(idx);
+                    let name = format!("{} {}{}", idx + 1, Output::buffer_name(editor_rows), if dirty > 0 { "*" } else { "" });
+                    if idx == selected { format!("[{}]", name) } else { name }
+                })
+                .collect::<Vec<_>>()
+                .join(" | ");
+            self.status_message.set_message(format!("Buffers: {} (Up/Down/Enter/ESC)", list));
+            self.refresh_screen()?;
+            match self.read_key(&Reader)?.code {
+                KeyCode::Up | KeyCode::Left => {
+                    selected = (selected + self.buffers.len() - 1) % self.buffers.len();
+                }
+                KeyCode::Down | KeyCode::Right | KeyCode::Tab => {
+                    selected = (selected + 1) % self.buffers.len();
+                }
+                KeyCode::Char(ch @ '1'..='9') => {
+                    let idx = (ch as usize) - ('1' as usize);
+                    if idx < self.buffers.len() {
+                        selected = idx;
+                    }
+                }
+                KeyCode::Enter => {
+                    self.switch_buffer(selected);
+                    break;
+                }
+                KeyCode::Esc => break,
+                _ => {}
+            }
+        }
+        self.status_message.set_message(String::new());
+        Ok(())
     }
 }
 
 fn main() -> crossterm::Result<()> {
     let _clean_up = CleanUp;
     terminal::enable_raw_mode()?;
+    execute!(stdout(), event::EnableMouseCapture)?;
     let mut editor = Editor::new();
     while editor.run()? {}
     Ok(())
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn output(rows: &[&str]) -> Output {
+        let win_size = (80, 20);
+        let mut editor_rows = EditorRows::new();
+        for (at, row) in rows.iter().enumerate() {
+            editor_rows.insert_row(at, row.to_string());
+        }
+        let buffers = vec![Buffer::new(editor_rows, win_size)];
+        Output::from_buffers(win_size, buffers, StatusMessage::new(String::new()), Config::default(), Syntaxes::load().0, Theme::default())
+    }
+
+    #[test]
+    fn undo_brings_back_an_empty_buffer() {
+        let mut output = output(&[]);
+        output.insert_text((0, 0), "a");
+        output.history.break_group();
+        output.insert_text((1, 0), "\nb");
+        assert_eq!(output.editor_rows.number_of_rows(), 2);
+        assert!(output.dirty > 0);
+        output.undo();
+        assert_eq!(output.editor_rows.number_of_rows(), 1);
+        assert!(output.dirty > 0);
+        output.undo();
+        assert_eq!(output.editor_rows.number_of_rows(), 0);
+        assert_eq!(output.dirty, 0);
+        output.redo();
+        assert_eq!(output.editor_rows.number_of_rows(), 1);
+        assert!(output.dirty > 0);
+    }
+
+    #[test]
+    fn deleting_the_text_of_a_row_keeps_the_row() {
+        let mut output = output(&["a"]);
+        output.delete_text((0, 0), (1, 0));
+        assert_eq!(output.editor_rows.number_of_rows(), 1);
+        output.undo();
+        output.redo();
+        assert_eq!(output.editor_rows.number_of_rows(), 1);
+        assert!(output.dirty > 0);
+    }
+
+    #[test]
+    fn dirty_follows_the_saved_position() {
+        let mut output = output(&["a"]);
+        output.insert_text((1, 0), "b");
+        output.history.mark_saved();
+        output.dirty = 0;
+        output.insert_text((2, 0), " c");
+        assert!(output.dirty > 0);
+        output.undo();
+        assert_eq!(output.dirty, 0);
+        output.undo();
+        assert!(output.dirty > 0);
+        // an insert of nothing changes nothing
+        output.redo();
+        output.insert_text((2, 0), "");
+        assert_eq!(output.dirty, 0);
+    }
+}
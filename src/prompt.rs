@@ -6,31 +6,44 @@ macro_rules! prompt {
       {prompt!($output, $args, callback = |&_, _, _| {})}
     };
     ($output:expr, $args:tt, callback = $callback:expr) => {
+      {prompt!($output, $args, callback = $callback, options = |_: &Output| String::new())}
+    };
+    // `options` is appended to the message, e.g. the search toggles
+    ($output:expr, $args:tt, callback = $callback:expr, options = $options:expr) => {
+      {prompt!($output, $args, callback = $callback, options = $options, empty = false)}
+    };
+    // with `empty = true` Enter accepts an empty input, only Esc gives None
+    ($output:expr, $args:tt, callback = $callback:expr, options = $options:expr, empty = $empty:expr) => {
         {
           let output:&mut Output = $output;
           let mut input = String::with_capacity(32);
+          let mut cancelled = false;
           loop {
-            output.status_message.set_message(format!($args,input));
+            let options: String = $options(&*output);
+            output.status_message.set_message(format!($args,input) + &options);
             output.refresh_screen()?;
-            let key_code = Reader.read_key()?;
+            let key_code = output.read_key(&Reader)?;
             match key_code {
               KeyEvent {
                 code: KeyCode::Enter,
                 modifiers: event::KeyModifiers::NONE,
-              } => {
-                if !input.is_empty() {
-                  output.status_message.set_message(String::new());
-                  $callback(output, &input, KeyCode::Enter);
-                  break;
-                }
+              } if $empty || !input.is_empty() => {
+                output.status_message.set_message(String::new());
+                $callback(output, &input, key_code);
+                break;
               },
               KeyEvent {
                 code: KeyCode::Esc,
                 modifiers: event::KeyModifiers::NONE,
+              } | KeyEvent {
+                // emacs `C-g`
+                code: KeyCode::Char('g'),
+                modifiers: event::KeyModifiers::CONTROL,
               } => {
                   output.status_message.set_message(String::new());
                   input.clear();
-                  $callback(output, &input, KeyCode::Esc);
+                  cancelled = true;
+                  $callback(output, &input, key_code);
                   break;
               },
               KeyEvent {
@@ -51,9 +64,9 @@ macro_rules! prompt {
               },
               _ => { },
             }
-            $callback(output, &input, key_code.code);
+            $callback(output, &input, key_code);
           }
-          if input.is_empty() { None } else { Some(input) }
+          if cancelled || (!$empty && input.is_empty()) { None } else { Some(input) }
         }
     };
-}
\ No newline at end of file
+}
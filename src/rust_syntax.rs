@@ -0,0 +1,310 @@
+use crate::editor_row::HighlightType;
+use crate::syntax_file::SyntaxFile;
+use crate::syntax_struct::{ LineState, SyntaxHighlight };
+
+// Rust 词法分析: lifetimes, raw / byte strings, nested comments, attributes and macros.
+// the keywords and their colors still come from `rust.toml`
+pub struct RustHighlight {
+    file: SyntaxFile,
+}
+
+fn is_ident_start(ch: char) -> bool {
+    ch == '_' || ch.is_alphabetic()
+}
+
+fn is_ident_continue(ch: char) -> bool {
+    ch == '_' || ch.is_alphanumeric()
+}
+
+// the end of the identifier starting at `from`
+fn ident_end(text: &str, from: usize) -> usize {
+    text[from..]
+        .char_indices()
+        .find(|&(_, ch)| !is_ident_continue(ch))
+        .map_or(text.len(), |(at, _)| from + at)
+}
+
+// after the closing `*/` of the comment `depth` deep, or the end of the row and the depth left
+fn comment_end(text: &str, from: usize, mut depth: usize) -> (usize, usize) {
+    let bytes = text.as_bytes();
+    let mut i = from;
+    while i < bytes.len() {
+        if bytes[i..].starts_with(b"/*") {
+            depth += 1;
+            i += 2;
+        } else if bytes[i..].starts_with(b"*/") {
+            depth -= 1;
+            i += 2;
+            if depth == 0 {
+                return (i, 0);
+            }
+        } else {
+            i += 1;
+        }
+    }
+    (bytes.len(), depth)
+}
+
+// after the closing `"`, None when the string goes on in the next row
+fn string_end(text: &str, from: usize) -> Option<usize> {
+    let bytes = text.as_bytes();
+    let mut i = from;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => i += 2,
+            b'"' => return Some(i + 1),
+            _ => i += 1,
+        }
+    }
+    None
+}
+
+// after the closing `"##`
+fn raw_string_end(text: &str, from: usize, hashes: usize) -> Option<usize> {
+    let bytes = text.as_bytes();
+    (from..bytes.len())
+        .find(|&i| bytes[i] == b'"' && bytes[i + 1..].iter().take(hashes).filter(|&&b| b == b'#').count() == hashes)
+        .map(|i| i + 1 + hashes)
+}
+
+// `b"`, `r#"`, `br"`, `c"`: the length up to the opening quote and the `#` of a raw string
+fn string_prefix(rest: &str) -> Option<(usize, Option<usize>)> {
+    for (prefix, raw) in [("br", true), ("cr", true), ("r", true), ("b", false), ("c", false)] {
+        let after = match rest.strip_prefix(prefix) {
+            Some(after) => after,
+            None => continue,
+        };
+        let hashes = if raw { after.bytes().take_while(|&b| b == b'#').count() } else { 0 };
+        if after[hashes..].starts_with('"') {
+            return Some((prefix.len() + hashes + 1, raw.then_some(hashes)));
+        }
+    }
+    None
+}
+
+impl RustHighlight {
+    pub fn new(file: SyntaxFile) -> Self {
+        Self { file }
+    }
+
+    // the token at `i` outside of comments and strings: (its end, highlight, state after it)
+    fn token(&self, text: &str, i: usize) -> (usize, HighlightType, LineState) {
+        let rest = &text[i..];
+        let ch = rest.chars().next().unwrap();
+        if rest.starts_with("//") {
+            return (text.len(), HighlightType::Comment, LineState::Normal);
+        }
+        if rest.starts_with("/*") {
+            return (i + 2, HighlightType::MultilineComment, LineState::Comment(1));
+        }
+        if ch == '"' {
+            return (i + 1, HighlightType::String, LineState::String);
+        }
+        if let Some((len, raw)) = string_prefix(rest) {
+            return (i + len, HighlightType::String, raw.map_or(LineState::String, LineState::RawString));
+        }
+        if rest.starts_with("b'") {
+            let (end, highlight) = self.quote(text, i + 1);
+            return (end, highlight, LineState::Normal);
+        }
+        if ch == '\'' {
+            let (end, highlight) = self.quote(text, i);
+            return (end, highlight, LineState::Normal);
+        }
+        if ch == '#' {
+            return match self.attribute_end(text, i) {
+                Some(end) => (end, HighlightType::Attribute, LineState::Normal),
+                None => (i + 1, HighlightType::Normal, LineState::Normal),
+            };
+        }
+        if ch.is_ascii_digit() {
+            return (self.number_end(text, i), HighlightType::Number, LineState::Normal);
+        }
+        // raw identifier, `r#type`
+        if rest.starts_with("r#") && rest[2..].chars().next().is_some_and(is_ident_start) {
+            return (ident_end(text, i + 2), HighlightType::Normal, LineState::Normal);
+        }
+        if is_ident_start(ch) {
+            let end = ident_end(text, i);
+            let after = &text[end..];
+            if after.starts_with('!') && !after.starts_with("!=") {
+                return (end + 1, HighlightType::Macro, LineState::Normal);
+            }
+            let highlight = self.file.keyword_highlight(&text[i..end]).unwrap_or(HighlightType::Normal);
+            return (end, highlight, LineState::Normal);
+        }
+        (i + ch.len_utf8(), HighlightType::Normal, LineState::Normal)
+    }
+
+    // `'x'`, `'\n'` or the lifetime / label `'a`
+    fn quote(&self, text: &str, i: usize) -> (usize, HighlightType) {
+        let rest = &text[i + 1..];
+        match rest.chars().next() {
+            Some('\\') => {
+                let from = i + 2 + rest[1..].chars().next().map_or(0, char::len_utf8);
+                let end = text[from..].find('\'').map_or(text.len(), |at| from + at + 1);
+                (end, HighlightType::CharLiteral)
+            }
+            Some(ch) if rest[ch.len_utf8()..].starts_with('\'') => (i + 2 + ch.len_utf8(), HighlightType::CharLiteral),
+            Some(ch) if is_ident_start(ch) => (ident_end(text, i + 1), HighlightType::Lifetime),
+            _ => (i + 1, HighlightType::Normal),
+        }
+    }
+
+    // `#[...]` / `#![...]` up to the matching `]`, the rest of the row when it isn't closed
+    fn attribute_end(&self, text: &str, i: usize) -> Option<usize> {
+        let bytes = text.as_bytes();
+        let open = if bytes[i + 1..].starts_with(b"!") { i + 2 } else { i + 1 };
+        if bytes.get(open) != Some(&b'[') {
+            return None;
+        }
+        let mut depth = 0;
+        let mut j = open;
+        while j < bytes.len() {
+            match bytes[j] {
+                b'[' => depth += 1,
+                b']' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Some(j + 1);
+                    }
+                }
+                b'"' => {
+                    j = string_end(text, j + 1).unwrap_or(bytes.len());
+                    continue;
+                }
+                _ => {}
+            }
+            j += 1;
+        }
+        Some(bytes.len())
+    }
+
+    // `42`, `0xff_u8`, `1.5e-3f64`, but not the `.` of `1..2` or `1.max(2)`
+    fn number_end(&self, text: &str, i: usize) -> usize {
+        let bytes = text.as_bytes();
+        let radix = bytes[i..].starts_with(b"0x") || bytes[i..].starts_with(b"0o") || bytes[i..].starts_with(b"0b");
+        let mut dot = false;
+        let mut j = i + 1;
+        while j < bytes.len() {
+            match bytes[j] {
+                b if b.is_ascii_alphanumeric() || b == b'_' => j += 1,
+                b'.' if !dot && !radix && bytes.get(j + 1).is_some_and(u8::is_ascii_digit) => {
+                    dot = true;
+                    j += 1;
+                }
+                b'+' | b'-' if !radix && matches!(bytes[j - 1], b'e' | b'E') => j += 1,
+                _ => break,
+            }
+        }
+        j
+    }
+}
+
+impl SyntaxHighlight for RustHighlight {
+    fn highlight_row(&self, render: &str, mut state: LineState, highlight: &mut Vec<HighlightType>) -> LineState {
+        highlight.clear();
+        highlight.reserve(render.len());
+        let len = render.len();
+        let mut i = 0;
+        while i < len {
+            // a comment or string may be left open by the row above
+            let (end, kind, next) = match state {
+                LineState::Comment(depth) => {
+                    let (end, depth) = comment_end(render, i, depth);
+                    let next = if depth == 0 { LineState::Normal } else { LineState::Comment(depth) };
+                    (end, HighlightType::MultilineComment, next)
+                }
+                LineState::String => match string_end(render, i) {
+                    Some(end) => (end, HighlightType::String, LineState::Normal),
+                    None => (len, HighlightType::String, LineState::String),
+                },
+                LineState::RawString(hashes) => match raw_string_end(render, i, hashes) {
+                    Some(end) => (end, HighlightType::String, LineState::Normal),
+                    None => (len, HighlightType::String, LineState::RawString(hashes)),
+                },
+                LineState::Normal => self.token(render, i),
+            };
+            let end = end.min(len);
+            highlight.resize(end, kind);
+            i = end;
+            state = next;
+        }
+        state
+    }
+
+    fn file_type(&self) -> &str {
+        &self.file.file_type
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // one letter per byte of `row` and the state left for the next row
+    fn highlight(row: &str, state: LineState) -> (String, LineState) {
+        let file = toml::from_str(include_str!("../syntaxes/rust.toml")).unwrap();
+        let mut highlight = Vec::new();
+        let state = RustHighlight::new(file).highlight_row(row, state, &mut highlight);
+        let kinds = highlight.iter().map(|kind| match kind {
+            HighlightType::Normal => '.',
+            HighlightType::Number => 'n',
+            HighlightType::String => 's',
+            HighlightType::CharLiteral => 'c',
+            HighlightType::Comment | HighlightType::MultilineComment => '/',
+            HighlightType::Lifetime => 'l',
+            HighlightType::Attribute => 'a',
+            HighlightType::Macro => 'm',
+            HighlightType::Keyword => 'k',
+            HighlightType::Type => 't',
+            _ => '?',
+        }).collect();
+        (kinds, state)
+    }
+
+    fn kinds(row: &str) -> String {
+        highlight(row, LineState::Normal).0
+    }
+
+    #[test]
+    fn lifetimes_and_char_literals() {
+        assert_eq!(kinds("&'a str"), ".ll.ttt");
+        assert_eq!(kinds("'x' '\\n' b'y'"), "ccc.cccc.cccc");
+        assert_eq!(kinds("'outer: loop"), "llllll..kkkk");
+        assert_eq!(kinds("'ß'"), "cccc");
+    }
+
+    #[test]
+    fn raw_and_byte_strings() {
+        assert_eq!(kinds(r##"r#"a"b"# x"##), "ssssssss..");
+        assert_eq!(kinds(r#"b"\"" c"x""#), "sssss.ssss");
+        assert_eq!(highlight(r##"br#"open"##, LineState::Normal), ("ssssssss".to_string(), LineState::RawString(1)));
+        assert_eq!(highlight(r##"a" b "#;"##, LineState::RawString(1)), ("sssssss.".to_string(), LineState::Normal));
+        assert_eq!(highlight(r#"a" b"#, LineState::RawString(1)), ("ssss".to_string(), LineState::RawString(1)));
+        assert_eq!(highlight(r#""a\"#, LineState::Normal).1, LineState::String);
+    }
+
+    #[test]
+    fn nested_comments() {
+        assert_eq!(highlight("x /* a /* b */", LineState::Normal), ("..////////////".to_string(), LineState::Comment(1)));
+        assert_eq!(highlight("*/ */ x", LineState::Comment(2)), ("/////..".to_string(), LineState::Normal));
+        assert_eq!(kinds("a // b"), "..////");
+    }
+
+    #[test]
+    fn numbers() {
+        assert_eq!(kinds("42 0xff_u8 1.5e-3f64"), "nn.nnnnnnn.nnnnnnnnn");
+        assert_eq!(kinds("1..2"), "n..n");
+        assert_eq!(kinds("1.max(2)"), "n.....n.");
+        assert_eq!(kinds("x1"), "..");
+    }
+
+    #[test]
+    fn attributes_macros_and_keywords() {
+        assert_eq!(kinds("#[derive(Debug)] fn"), "aaaaaaaaaaaaaaaa.kk");
+        assert_eq!(kinds("#![allow(\"]\")]"), "aaaaaaaaaaaaaa");
+        assert_eq!(kinds("vec![x] a != b"), "mmmm..........");
+        assert_eq!(kinds("r#type type"), ".......kkkk");
+    }
+}
@@ -0,0 +1,138 @@
+use std::io::{ self, Write };
+use crossterm::{ cursor, queue, terminal::{ self, ClearType }, style::{ Attribute, Color, SetAttribute, SetBackgroundColor, SetForegroundColor } };
+use unicode_segmentation::UnicodeSegmentation;
+use crate::editor_row::grapheme_width;
+use crate::theme::Style;
+
+#[derive(Clone, PartialEq)]
+struct Cell {
+    symbol: String, // empty for the second column of a wide character
+    style: Style,
+}
+
+impl Cell {
+    fn blank() -> Self {
+        Self { symbol: " ".to_string(), style: Style::default() }
+    }
+}
+
+// 屏幕缓冲: a frame is drawn into cells, `render` only writes the cells that changed since the last frame
+pub struct Screen {
+    width: usize,
+    height: usize,
+    cells: Vec<Cell>,
+    previous: Option<Vec<Cell>>, // what the terminal shows, `None` until the first frame or after a resize
+    x: usize,
+    y: usize,
+    style: Style,
+}
+
+impl Screen {
+    pub fn new() -> Self {
+        Self {
+            width: 0,
+            height: 0,
+            cells: Vec::new(),
+            previous: None,
+            x: 0,
+            y: 0,
+            style: Style::default(),
+        }
+    }
+
+    // starts an empty frame
+    pub fn begin(&mut self, width: usize, height: usize) {
+        if (width, height) != (self.width, self.height) {
+            self.width = width;
+            self.height = height;
+            self.previous = None;
+        }
+        self.cells = vec![Cell::blank(); width * height];
+        self.move_to(0, 0);
+        self.style = Style::default();
+    }
+
+    pub fn move_to(&mut self, x: usize, y: usize) {
+        self.x = x;
+        self.y = y;
+    }
+
+    pub fn set_style(&mut self, style: Style) {
+        self.style = style;
+    }
+
+    pub fn push(&mut self, ch: char) {
+        self.push_str(ch.encode_utf8(&mut [0; 4]));
+    }
+
+    // text past the right edge is cut off
+    pub fn push_str(&mut self, text: &str) {
+        for g in text.graphemes(true) {
+            let width = grapheme_width(g);
+            if width == 0 {
+                continue;
+            }
+            if self.y < self.height && self.x + width <= self.width {
+                self.put(g, width);
+            }
+            self.x += width;
+        }
+    }
+
+    fn put(&mut self, g: &str, width: usize) {
+        let row_start = self.y * self.width;
+        let idx = row_start + self.x;
+        let style = self.style;
+        let cell = |symbol: &str| Cell { symbol: symbol.to_string(), style };
+        // never leave half of a wide character behind
+        if self.cells[idx].symbol.is_empty() && idx > row_start {
+            self.cells[idx - 1] = cell(" ");
+        }
+        if self.cells.get(idx + width).is_some_and(|next| next.symbol.is_empty()) && self.x + width < self.width {
+            self.cells[idx + width] = cell(" ");
+        }
+        self.cells[idx] = cell(g);
+        for idx in idx + 1..idx + width {
+            self.cells[idx] = cell("");
+        }
+    }
+
+    // writes out the difference to the last frame
+    pub fn render(&mut self, out: &mut impl Write) -> io::Result<()> {
+        queue!(out, SetAttribute(Attribute::Reset), SetForegroundColor(Color::Reset), SetBackgroundColor(Color::Reset))?;
+        let mut style = Style::default();
+        let previous = match self.previous.take() {
+            Some(previous) => previous,
+            None => {
+                queue!(out, terminal::Clear(ClearType::All))?;
+                vec![Cell::blank(); self.cells.len()]
+            }
+        };
+        // where the terminal cursor is after the last write
+        let mut position = None;
+        for (idx, cell) in self.cells.iter().enumerate() {
+            if *cell == previous[idx] || cell.symbol.is_empty() {
+                continue;
+            }
+            let (x, y) = (idx % self.width, idx / self.width);
+            if position != Some((x, y)) {
+                queue!(out, cursor::MoveTo(x as u16, y as u16))?;
+            }
+            if cell.style.foreground != style.foreground {
+                queue!(out, SetForegroundColor(cell.style.foreground))?;
+            }
+            if cell.style.background != style.background {
+                queue!(out, SetBackgroundColor(cell.style.background))?;
+            }
+            if cell.style.reverse != style.reverse {
+                queue!(out, SetAttribute(if cell.style.reverse { Attribute::Reverse } else { Attribute::NoReverse }))?;
+            }
+            style = cell.style;
+            out.write_all(cell.symbol.as_bytes())?;
+            position = Some((x + grapheme_width(&cell.symbol), y));
+        }
+        queue!(out, SetAttribute(Attribute::Reset), SetForegroundColor(Color::Reset), SetBackgroundColor(Color::Reset))?;
+        self.previous = Some(std::mem::take(&mut self.cells));
+        Ok(())
+    }
+}
@@ -1,34 +1,126 @@
-use crate::editor_row::HighlightType;
+use regex::{ Regex, RegexBuilder };
 
 // 向前向后搜索
+#[derive(Clone, Copy, PartialEq)]
 pub enum SearchDirection {
   Forward,
   Backward,
 }
 
+// (row, start, end), bytes of the row's render
+pub type Match = (usize, usize, usize);
+
 pub struct SearchIndex {
-  pub x_index: usize,
-  pub y_index: usize,
-  pub x_direction: Option<SearchDirection>,
-  pub y_direction: Option<SearchDirection>,
-  pub previous_highlight: Option<(usize, Vec<HighlightType>)>,
+  // the current match, drawn reversed
+  pub match_range: Option<Match>,
+  // while the prompt is open every visible match is highlighted
+  pub regex: Option<Regex>,
+  // (number of the current match, total)
+  pub count: Option<(usize, usize)>,
+  pub wrapped: bool,
+  // the keyword isn't a valid regular expression
+  pub invalid: bool,
+  // Alt-C / Alt-W in the prompt, kept between searches
+  pub ignore_case: bool,
+  pub whole_word: bool,
+  // emacs `C-s` / `C-r`: the direction and where the search started, (row, render byte)
+  pub origin: Option<(SearchDirection, (usize, usize))>,
 }
 
 impl SearchIndex {
   pub fn new() -> Self {
     Self {
-      x_index: 0,
-      y_index: 0,
-      x_direction: None,
-      y_direction: None,
-      previous_highlight: None,
+      match_range: None,
+      regex: None,
+      count: None,
+      wrapped: false,
+      invalid: false,
+      ignore_case: false,
+      whole_word: false,
+      origin: None,
     }
   }
   pub fn reset(&mut self) {
-    self.x_index = 0;
-    self.y_index = 0;
-    self.x_direction = None;
-    self.y_direction = None;
-    self.previous_highlight = None;
+    self.match_range = None;
+    self.regex = None;
+    self.count = None;
+    self.wrapped = false;
+    self.invalid = false;
+  }
+  // the keyword is a regular expression, None while it doesn't compile
+  pub fn regex(&self, keyword: &str) -> Option<Regex> {
+    let pattern = if self.whole_word { format!(r"\b(?:{})\b", keyword) } else { keyword.to_string() };
+    RegexBuilder::new(&pattern).case_insensitive(self.ignore_case).build().ok()
+  }
+  // the match after / before `match_range`, wrapping around the file.
+  // `by_row` skips the other matches of the current row
+  pub fn step(&mut self, matches: &[Match], direction: Option<SearchDirection>, by_row: bool) -> Option<usize> {
+    self.wrapped = false;
+    if matches.is_empty() {
+      return None;
+    }
+    let (row, start, _) = match (self.match_range, direction.as_ref()) {
+      (Some(current), Some(_)) => current,
+      _ => return Some(self.first_from_origin(matches).unwrap_or(0)),
+    };
+    let found = match direction {
+      Some(SearchDirection::Forward) if by_row => matches.iter().position(|m| m.0 > row),
+      Some(SearchDirection::Forward) => matches.iter().position(|m| (m.0, m.1) > (row, start)),
+      Some(SearchDirection::Backward) if by_row => matches
+        .iter()
+        .rposition(|m| m.0 < row)
+        .and_then(|idx| matches.iter().position(|m| m.0 == matches[idx].0)),
+      _ => matches.iter().rposition(|m| (m.0, m.1) < (row, start)),
+    };
+    found.or_else(|| {
+      self.wrapped = true;
+      match direction {
+        Some(SearchDirection::Forward) => Some(0),
+        _ if by_row => matches.iter().position(|m| m.0 == matches[matches.len() - 1].0),
+        _ => Some(matches.len() - 1),
+      }
+    })
+  }
+  // typing in an incremental search stays at the current match while it still matches,
+  // otherwise takes the next one from where the search started
+  fn first_from_origin(&mut self, matches: &[Match]) -> Option<usize> {
+    let (direction, origin) = self.origin?;
+    let (from, inclusive) = match self.match_range {
+      Some((row, start, _)) => ((row, start), true),
+      None => (origin, false),
+    };
+    let found = match direction {
+      SearchDirection::Forward => matches.iter().position(|m| (m.0, m.1) >= from),
+      SearchDirection::Backward => matches.iter().rposition(|m| (m.0, m.1) < from || (inclusive && (m.0, m.1) == from)),
+    };
+    found.or_else(|| {
+      self.wrapped = true;
+      match direction {
+        SearchDirection::Forward => Some(0),
+        SearchDirection::Backward => Some(matches.len() - 1),
+      }
+    })
+  }
+  // shown after the prompt
+  pub fn options(&self) -> String {
+    let mut options = String::new();
+    if self.ignore_case {
+      options.push_str(" [ignore case]");
+    }
+    if self.whole_word {
+      options.push_str(" [whole word]");
+    }
+    if self.invalid {
+      options.push_str(" -- invalid pattern");
+    }
+    match self.count {
+      Some((_, 0)) => options.push_str(" -- no matches"),
+      Some((current, total)) => options.push_str(&format!(" -- match {} of {}", current, total)),
+      None => {}
+    }
+    if self.wrapped {
+      options.push_str(" (wrapped)");
+    }
+    options
   }
-}
\ No newline at end of file
+}
@@ -3,13 +3,16 @@ use std::time::{ Duration, Instant };
 pub struct StatusMessage {
     pub message: Option<String>,
     pub set_time: Option<Instant>,
+    pub timeout: Duration,
 }
 
 impl StatusMessage {
     pub fn new(initial_message: String) -> Self {
         Self {
             message: Some(initial_message),
-            set_time: None,
+            // shown from the start like any other message
+            set_time: Some(Instant::now()),
+            timeout: Duration::from_secs(5),
         }
     }
     pub fn set_message(&mut self, message: String) {
@@ -18,7 +21,7 @@ impl StatusMessage {
     }
     pub fn message(&mut self) -> Option<&String> {
       self.set_time.and_then(|time| {
-        if time.elapsed() > Duration::from_secs(5) {
+        if time.elapsed() > self.timeout {
           self.message = None;
           self.set_time = None;
           None
@@ -28,4 +31,17 @@ impl StatusMessage {
         }
       })
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_initial_message_is_shown_until_the_timeout() {
+        let mut status_message = StatusMessage::new("HELP: C-q = Quit".to_string());
+        assert_eq!(status_message.message().map(String::as_str), Some("HELP: C-q = Quit"));
+        status_message.timeout = Duration::ZERO;
+        std::thread::sleep(Duration::from_millis(1));
+        assert_eq!(status_message.message(), None);
+    }
+}
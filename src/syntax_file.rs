@@ -0,0 +1,441 @@
+use std::{ cmp, fs, iter, path::Path };
+use crossterm::style::Color;
+use serde::{ de::Error, Deserialize, Deserializer };
+use crate::config::Config;
+use crate::rust_syntax::RustHighlight;
+use crate::editor_row::{ EditorRows, HighlightType };
+use crate::syntax_struct::{ LineState, SyntaxHighlight };
+use crate::theme;
+
+// shipped in the binary, `~/.config/pound/syntaxes` may replace them
+const BUILT_IN: [(&str, &str); 14] = [
+    ("rust.toml", include_str!("../syntaxes/rust.toml")),
+    ("c.toml", include_str!("../syntaxes/c.toml")),
+    ("cpp.toml", include_str!("../syntaxes/cpp.toml")),
+    ("python.toml", include_str!("../syntaxes/python.toml")),
+    ("javascript.toml", include_str!("../syntaxes/javascript.toml")),
+    ("typescript.toml", include_str!("../syntaxes/typescript.toml")),
+    ("go.toml", include_str!("../syntaxes/go.toml")),
+    ("toml.toml", include_str!("../syntaxes/toml.toml")),
+    ("yaml.toml", include_str!("../syntaxes/yaml.toml")),
+    ("json.toml", include_str!("../syntaxes/json.toml")),
+    ("markdown.toml", include_str!("../syntaxes/markdown.toml")),
+    ("shell.toml", include_str!("../syntaxes/shell.toml")),
+    ("makefile.toml", include_str!("../syntaxes/makefile.toml")),
+    ("dockerfile.toml", include_str!("../syntaxes/dockerfile.toml")),
+];
+
+// 语法定义文件, TOML or JSON
+#[derive(Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SyntaxFile {
+    pub file_type: String,
+    #[serde(default)]
+    pub extensions: Vec<String>,
+    #[serde(default)]
+    pub filenames: Vec<String>, // whole names like `Makefile`, before the extension
+    #[serde(default)]
+    pub shebangs: Vec<String>, // interpreters of a `#!` first line, `python` also takes `python3.11`
+    #[serde(default)]
+    pub comment_start: String,
+    #[serde(default)]
+    pub multiline_comment: Option<(String, String)>,
+    #[serde(default = "SyntaxFile::default_strings")]
+    pub strings: Vec<char>, // delimiters of string literals
+    #[serde(default)]
+    pub chars: Vec<char>, // delimiters of char literals, `'` in Rust
+    #[serde(default)]
+    pub keywords: Vec<Keywords>,
+    #[serde(default)]
+    pub tokenizer: Option<Tokenizer>, // lexed in code instead, only the keywords above are used
+}
+
+#[derive(Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Tokenizer {
+    Rust,
+}
+
+// 关键字组, drawn in the theme style of its scope, the color is for groups without one
+#[derive(Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Keywords {
+    #[serde(default)]
+    pub scope: Option<Scope>,
+    #[serde(default, deserialize_with = "deserialize_color")]
+    pub color: Option<Color>,
+    pub words: Vec<String>,
+}
+
+#[derive(Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Scope {
+    Keyword,
+    Type,
+}
+
+impl Keywords {
+    fn highlight(&self) -> HighlightType {
+        match (self.scope, self.color) {
+            (Some(Scope::Keyword), _) => HighlightType::Keyword,
+            (Some(Scope::Type), _) => HighlightType::Type,
+            (None, Some(color)) => HighlightType::Other(color),
+            (None, None) => HighlightType::Normal,
+        }
+    }
+}
+
+fn deserialize_color<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Color>, D::Error> {
+    let name = String::deserialize(deserializer)?;
+    theme::parse_color(&name).map(Some).ok_or_else(|| D::Error::custom(format!("unknown color `{}`", name)))
+}
+
+impl SyntaxFile {
+    fn default_strings() -> Vec<char> {
+        vec!['"']
+    }
+
+    fn parse(name: &str, text: &str) -> Result<Self, String> {
+        let file: SyntaxFile = if name.ends_with(".json") {
+            serde_json::from_str(text).map_err(|err| format!("line {}: {}", err.line(), err))?
+        } else {
+            toml::from_str(text).map_err(|err| Config::describe_error(text, err))?
+        };
+        if let Some(keywords) = file.keywords.iter().find(|keywords| keywords.scope.is_none() && keywords.color.is_none()) {
+            let first = keywords.words.first().map(String::as_str).unwrap_or_default();
+            return Err(format!("keywords `{}`: expected a `scope` or a `color`", first));
+        }
+        Ok(file)
+    }
+
+    pub fn keyword_highlight(&self, word: &str) -> Option<HighlightType> {
+        self.keywords.iter().find(|keywords| keywords.words.iter().any(|it| it == word)).map(Keywords::highlight)
+    }
+
+    // the keyword starting at byte `i` of `render`, it has to end at a separator
+    fn keyword_at(&self, render: &str, i: usize) -> Option<(HighlightType, usize)> {
+        for keywords in &self.keywords {
+            for word in &keywords.words {
+                let end = i + word.len();
+                if render.as_bytes().get(i..end) != Some(word.as_bytes()) {
+                    continue;
+                }
+                // the word matched whole characters, `end` is on a character boundary
+                let is_end_sep = render[end..].chars().next().is_none_or(|ch| self.is_separator(ch));
+                if is_end_sep {
+                    return Some((keywords.highlight(), word.len()));
+                }
+            }
+        }
+        None
+    }
+}
+
+impl SyntaxHighlight for SyntaxFile {
+    fn highlight_row(&self, render: &str, state: LineState, highlight: &mut Vec<HighlightType>) -> LineState {
+        let mut in_comment = matches!(state, LineState::Comment(_));
+        highlight.clear();
+        highlight.reserve(render.len());
+        let bytes = render.as_bytes();
+        let render_len = render.len();
+        let comment_start = self.comment_start.as_bytes();
+        let starts_with = |i: usize, token: &[u8]| bytes[i..cmp::min(i + token.len(), render_len)] == *token;
+        // one highlight per byte, a character of several bytes gets the same one for each
+        let push = |highlight: &mut Vec<HighlightType>, len: usize, kind: HighlightType| {
+            highlight.extend(iter::repeat_n(kind, len));
+        };
+
+        let mut i = 0;
+        let mut previous_separator = true;
+        let mut in_string: Option<char> = None;
+        while i < render_len {
+            let ch = render[i..].chars().next().unwrap();
+            let len = ch.len_utf8();
+            let previous_highlight = if i > 0 { highlight[i - 1] } else { HighlightType::Normal };
+
+            if let Some(delimiter) = in_string {
+                let string = if self.chars.contains(&delimiter) { HighlightType::CharLiteral } else { HighlightType::String };
+                push(highlight, len, string);
+                if ch == '\\' && i + 1 < render_len {
+                    // the escaped character
+                    let escaped = render[i + 1..].chars().next().unwrap().len_utf8();
+                    push(highlight, escaped, string);
+                    i += 1 + escaped;
+                    continue;
+                }
+                if ch == delimiter {
+                    in_string = None;
+                }
+                i += len;
+                previous_separator = true;
+                continue;
+            }
+
+            if let (true, Some((_, end))) = (in_comment, self.multiline_comment.as_ref()) {
+                if starts_with(i, end.as_bytes()) {
+                    push(highlight, end.len(), HighlightType::MultilineComment);
+                    i += end.len();
+                    previous_separator = true;
+                    in_comment = false;
+                } else {
+                    push(highlight, len, HighlightType::MultilineComment);
+                    i += len;
+                }
+                continue;
+            }
+
+            if !comment_start.is_empty() && starts_with(i, comment_start) {
+                push(highlight, render_len - i, HighlightType::Comment);
+                break;
+            }
+
+            if let Some((start, _)) = self.multiline_comment.as_ref() {
+                if starts_with(i, start.as_bytes()) {
+                    push(highlight, start.len(), HighlightType::MultilineComment);
+                    i += start.len();
+                    in_comment = true;
+                    continue;
+                }
+            }
+
+            if self.strings.contains(&ch) || self.chars.contains(&ch) {
+                in_string = Some(ch);
+                push(highlight, len, if self.chars.contains(&ch) { HighlightType::CharLiteral } else { HighlightType::String });
+                i += len;
+                continue;
+            }
+
+            if
+                (ch.is_ascii_digit() && (previous_separator || matches!(previous_highlight, HighlightType::Number))) ||
+                (ch == '.' && matches!(previous_highlight, HighlightType::Number))
+            {
+                highlight.push(HighlightType::Number);
+                i += 1;
+                previous_separator = false;
+                continue;
+            }
+
+            // 关键字
+            if previous_separator {
+                if let Some((keyword, len)) = self.keyword_at(render, i) {
+                    push(highlight, len, keyword);
+                    i += len;
+                    previous_separator = false;
+                    continue;
+                }
+            }
+
+            push(highlight, len, HighlightType::Normal);
+            previous_separator = self.is_separator(ch);
+            i += len;
+        }
+        // /* ml_comment 之间全部注释*/
+        if in_comment { LineState::Comment(1) } else { LineState::Normal }
+    }
+
+    fn file_type(&self) -> &str {
+        &self.file_type
+    }
+}
+
+// the program of a `#!/usr/bin/env python3` line, `python3`
+fn interpreter(first_line: &str) -> Option<&str> {
+    let mut words = first_line.strip_prefix("#!")?.split_whitespace();
+    let mut program = words.next()?.rsplit('/').next()?;
+    if program == "env" {
+        program = words.find(|word| !word.starts_with('-') && !word.contains('='))?;
+    }
+    Some(program)
+}
+
+// every known syntax, picked by file name, extension or shebang
+pub struct Syntaxes {
+    files: Vec<SyntaxFile>,
+}
+
+impl Syntaxes {
+    // the built-in files, then `*.toml` / `*.json` of the `syntaxes` directory in name order.
+    // a file of a known file_type replaces it, the problems found are returned
+    pub fn load() -> (Self, Vec<String>) {
+        let mut syntaxes = Syntaxes { files: Vec::new() };
+        let mut errors = Vec::new();
+        for (name, text) in BUILT_IN {
+            match SyntaxFile::parse(name, text) {
+                Ok(file) => syntaxes.add(file),
+                Err(err) => errors.push(format!("{}: {}", name, err)),
+            }
+        }
+        let mut paths: Vec<_> = Config::dir()
+            .and_then(|dir| fs::read_dir(dir.join("syntaxes")).ok())
+            .into_iter()
+            .flatten()
+            .filter_map(|entry| Some(entry.ok()?.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "toml" || ext == "json"))
+            .collect();
+        paths.sort();
+        for path in paths {
+            let text = match fs::read_to_string(&path) {
+                Ok(text) => text,
+                Err(err) => {
+                    errors.push(format!("{}: {}", path.display(), err));
+                    continue;
+                }
+            };
+            match SyntaxFile::parse(&path.to_string_lossy(), &text) {
+                Ok(file) => syntaxes.add(file),
+                Err(err) => errors.push(format!("{}: {}", path.display(), err)),
+            }
+        }
+        (syntaxes, errors)
+    }
+
+    fn add(&mut self, file: SyntaxFile) {
+        self.files.retain(|it| it.file_type != file.file_type);
+        self.files.push(file);
+    }
+
+    // by the file name of the buffer, then the shebang on its first row
+    pub fn for_rows(&self, editor_rows: &EditorRows) -> Option<Box<dyn SyntaxHighlight>> {
+        let first_line = if editor_rows.number_of_rows() > 0 {
+            Some(editor_rows.get_editor_row(0).row_content)
+        } else {
+            None
+        };
+        let path = editor_rows.filename.as_deref().unwrap_or(Path::new(""));
+        self.for_file(path, first_line.as_deref())
+    }
+
+    fn for_file(&self, path: &Path, first_line: Option<&str>) -> Option<Box<dyn SyntaxHighlight>> {
+        let name = path.file_name().and_then(|name| name.to_str()).unwrap_or("");
+        let extension = path.extension().and_then(|ext| ext.to_str());
+        let program = first_line.and_then(interpreter);
+        let by_shebang = |file: &&SyntaxFile| {
+            program.is_some_and(|program| {
+                file.shebangs.iter().any(|it| {
+                    program.strip_prefix(it.as_str()).is_some_and(|version| version.chars().all(|ch| ch.is_ascii_digit() || ch == '.'))
+                })
+            })
+        };
+        // later files win, they replace the built-in ones
+        let file = self.files.iter().rev().find(|file| file.filenames.iter().any(|it| it == name))
+            .or_else(|| self.files.iter().rev().find(|file| extension.is_some_and(|ext| file.extensions.iter().any(|it| it == ext))))
+            .or_else(|| self.files.iter().rev().find(by_shebang))?;
+        Some(match file.tokenizer {
+            Some(Tokenizer::Rust) => Box::new(RustHighlight::new(file.clone())),
+            None => Box::new(file.clone()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn python() -> SyntaxFile {
+        SyntaxFile::parse("python.toml", include_str!("../syntaxes/python.toml")).unwrap()
+    }
+
+    // one letter per byte of `row`
+    fn kinds(file: &SyntaxFile, row: &str) -> String {
+        let mut highlight = Vec::new();
+        file.highlight_row(row, LineState::Normal, &mut highlight);
+        assert_eq!(highlight.len(), row.len());
+        highlight.iter().map(|kind| match kind {
+            HighlightType::Normal => '.',
+            HighlightType::Number => 'n',
+            HighlightType::String => 's',
+            HighlightType::Comment => '/',
+            HighlightType::Keyword => 'k',
+            HighlightType::Type => 't',
+            _ => '?',
+        }).collect()
+    }
+
+    // the built-in files only, a config directory can't get in the way
+    fn built_in() -> Syntaxes {
+        let mut syntaxes = Syntaxes { files: Vec::new() };
+        for (name, text) in BUILT_IN {
+            syntaxes.add(SyntaxFile::parse(name, text).unwrap());
+        }
+        syntaxes
+    }
+
+    fn file_type(syntaxes: &Syntaxes, path: &str, first_line: &str) -> Option<String> {
+        let syntax = syntaxes.for_file(Path::new(path), Some(first_line))?;
+        Some(syntax.file_type().to_string())
+    }
+
+    #[test]
+    fn whole_names() {
+        let syntaxes = built_in();
+        assert_eq!(file_type(&syntaxes, "Cargo.lock", "").as_deref(), Some("toml"));
+        assert_eq!(file_type(&syntaxes, "docker/Dockerfile", "").as_deref(), Some("dockerfile"));
+        assert_eq!(file_type(&syntaxes, "/src/Makefile", "").as_deref(), Some("makefile"));
+        assert_eq!(file_type(&syntaxes, "GNUmakefile", "").as_deref(), Some("makefile"));
+        assert_eq!(file_type(&syntaxes, "Makefile.bak", "").as_deref(), None);
+        assert_eq!(file_type(&syntaxes, "README", "").as_deref(), None);
+    }
+
+    #[test]
+    fn name_then_extension_then_shebang() {
+        let mut syntaxes = built_in();
+        syntaxes.add(SyntaxFile::parse("build.toml", "file_type = \"build\"\nfilenames = [\"build.rs\"]").unwrap());
+        assert_eq!(file_type(&syntaxes, "build.rs", "").as_deref(), Some("build"));
+        assert_eq!(file_type(&syntaxes, "main.rs", "").as_deref(), Some("rust"));
+        // the extension wins over the shebang
+        assert_eq!(file_type(&syntaxes, "run.py", "#!/bin/bash").as_deref(), Some("python"));
+        assert_eq!(file_type(&syntaxes, "run", "#!/bin/bash").as_deref(), Some("shell"));
+        assert_eq!(file_type(&syntaxes, "Makefile", "#!/usr/bin/env python3").as_deref(), Some("makefile"));
+    }
+
+    #[test]
+    fn later_files_replace_the_built_in_ones() {
+        let mut syntaxes = built_in();
+        syntaxes.add(SyntaxFile::parse("mine.toml", "file_type = \"mine\"\nextensions = [\"rs\"]").unwrap());
+        assert_eq!(file_type(&syntaxes, "main.rs", "").as_deref(), Some("mine"));
+        syntaxes.add(SyntaxFile::parse("rust.toml", "file_type = \"rust\"\nextensions = [\"rs\"]").unwrap());
+        assert_eq!(file_type(&syntaxes, "main.rs", "").as_deref(), Some("rust"));
+    }
+
+    #[test]
+    fn shebangs() {
+        let syntaxes = built_in();
+        assert_eq!(interpreter("#!/usr/bin/env python3"), Some("python3"));
+        assert_eq!(interpreter("#!/usr/bin/env -S python3.11 -u"), Some("python3.11"));
+        assert_eq!(interpreter("#!/usr/bin/env FOO=1 bash"), Some("bash"));
+        assert_eq!(interpreter("#! /bin/sh -e"), Some("sh"));
+        assert_eq!(interpreter("# not a shebang"), None);
+        assert_eq!(interpreter("#!/usr/bin/env"), None);
+        assert_eq!(file_type(&syntaxes, "run", "#!/usr/bin/env python3").as_deref(), Some("python"));
+        assert_eq!(file_type(&syntaxes, "run", "#!/usr/bin/python3.11").as_deref(), Some("python"));
+        assert_eq!(file_type(&syntaxes, "run", "#!/usr/bin/make -f").as_deref(), Some("makefile"));
+        // a version may follow the name, other letters may not
+        assert_eq!(file_type(&syntaxes, "run", "#!/usr/bin/env pythonista").as_deref(), None);
+        assert_eq!(file_type(&syntaxes, "run", "#!/bin/bashful").as_deref(), None);
+    }
+
+    #[test]
+    fn non_ascii_letters_are_part_of_words() {
+        let python = python();
+        // the second byte of `à` is 0xa0, a no-break space in Latin-1
+        assert_eq!(kinds(&python, "àin"), "....");
+        assert_eq!(kinds(&python, "ifà"), "....");
+        assert_eq!(kinds(&python, "à in é"), "...kk...");
+        assert_eq!(kinds(&python, "if\u{a0}x"), "kk...");
+    }
+
+    #[test]
+    fn non_ascii_strings_and_comments() {
+        let python = python();
+        assert_eq!(kinds(&python, "'héllo' 1"), "ssssssss.n");
+        assert_eq!(kinds(&python, "'\\é' x"), "sssss..");
+        assert_eq!(kinds(&python, "ü # é"), "...////");
+        assert_eq!(kinds(&python, "\"中文\" if"), "ssssssss.kk");
+    }
+
+    #[test]
+    fn non_ascii_delimiters() {
+        let file: SyntaxFile = toml::from_str("file_type = \"x\"\nstrings = [\"«\"]").unwrap();
+        assert_eq!(kinds(&file, "«a« b"), "sssss..");
+    }
+}
@@ -0,0 +1,394 @@
+use std::{ env, fs };
+use crossterm::style::Color;
+use serde::Deserialize;
+use crate::config::Config;
+use crate::editor_row::HighlightType;
+
+// shipped in the binary, a file of the same name in `~/.config/pound/themes` replaces them
+const BUILT_IN: [(&str, &str); 3] = [
+    ("default", include_str!("../themes/default.toml")),
+    ("light", include_str!("../themes/light.toml")),
+    ("gruvbox", include_str!("../themes/gruvbox.toml")),
+];
+
+// 终端支持的颜色
+#[derive(Clone, Copy, PartialEq, Default)]
+pub enum ColorDepth {
+    #[default]
+    TrueColor,
+    Ansi256,
+    Ansi16,
+}
+
+// the 16 colors as xterm shows them, to find the nearest one
+const ANSI_16: [(Color, (u8, u8, u8)); 16] = [
+    (Color::Black, (0, 0, 0)),
+    (Color::DarkRed, (205, 0, 0)),
+    (Color::DarkGreen, (0, 205, 0)),
+    (Color::DarkYellow, (205, 205, 0)),
+    (Color::DarkBlue, (0, 0, 238)),
+    (Color::DarkMagenta, (205, 0, 205)),
+    (Color::DarkCyan, (0, 205, 205)),
+    (Color::Grey, (229, 229, 229)),
+    (Color::DarkGrey, (127, 127, 127)),
+    (Color::Red, (255, 0, 0)),
+    (Color::Green, (0, 255, 0)),
+    (Color::Yellow, (255, 255, 0)),
+    (Color::Blue, (92, 92, 255)),
+    (Color::Magenta, (255, 0, 255)),
+    (Color::Cyan, (0, 255, 255)),
+    (Color::White, (255, 255, 255)),
+];
+
+// the steps of the 6x6x6 cube of the 256 colors
+const CUBE: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+fn distance((r1, g1, b1): (u8, u8, u8), (r2, g2, b2): (u8, u8, u8)) -> u32 {
+    let d = |a: u8, b: u8| (a as i32 - b as i32).pow(2) as u32;
+    d(r1, r2) + d(g1, g2) + d(b1, b2)
+}
+
+fn ansi_to_rgb(value: u8) -> (u8, u8, u8) {
+    match value {
+        0..=15 => ANSI_16[value as usize].1,
+        16..=231 => {
+            let value = value - 16;
+            (CUBE[(value / 36) as usize], CUBE[(value / 6 % 6) as usize], CUBE[(value % 6) as usize])
+        }
+        _ => {
+            let grey = 8 + (value - 232) * 10;
+            (grey, grey, grey)
+        }
+    }
+}
+
+fn rgb_to_ansi((r, g, b): (u8, u8, u8)) -> u8 {
+    let step = |v: u8| (0..CUBE.len()).min_by_key(|&i| (CUBE[i] as i32 - v as i32).abs()).unwrap() as u8;
+    let cube = 16 + 36 * step(r) + 6 * step(g) + step(b);
+    // or one of the 24 greys
+    let average = (r as u32 + g as u32 + b as u32) / 3;
+    let grey = 232 + (average.saturating_sub(3) / 10).min(23) as u8;
+    if distance((r, g, b), ansi_to_rgb(grey)) < distance((r, g, b), ansi_to_rgb(cube)) { grey } else { cube }
+}
+
+impl ColorDepth {
+    // `COLORTERM=truecolor` or `24bit`, else a `TERM` like `xterm-256color`, else 16 colors
+    pub fn detect() -> Self {
+        let colorterm = env::var("COLORTERM").unwrap_or_default();
+        if colorterm == "truecolor" || colorterm == "24bit" {
+            return ColorDepth::TrueColor;
+        }
+        if env::var("TERM").is_ok_and(|term| term.contains("256color")) {
+            return ColorDepth::Ansi256;
+        }
+        ColorDepth::Ansi16
+    }
+
+    // the nearest color the terminal can show, the named ones work everywhere
+    pub fn convert(self, color: Color) -> Color {
+        match (self, color) {
+            (ColorDepth::Ansi256, Color::Rgb { r, g, b }) => Color::AnsiValue(rgb_to_ansi((r, g, b))),
+            (ColorDepth::Ansi16, Color::Rgb { r, g, b }) => ColorDepth::nearest_16((r, g, b)),
+            (ColorDepth::Ansi16, Color::AnsiValue(value)) => ColorDepth::nearest_16(ansi_to_rgb(value)),
+            (_, color) => color,
+        }
+    }
+
+    fn nearest_16(rgb: (u8, u8, u8)) -> Color {
+        ANSI_16.iter().min_by_key(|(_, it)| distance(rgb, *it)).unwrap().0
+    }
+}
+
+// 前景, 背景 and reverse video of some text
+#[derive(Clone, Copy, PartialEq, Deserialize)]
+#[serde(try_from = "StyleFile")]
+pub struct Style {
+    pub foreground: Color,
+    pub background: Color,
+    pub reverse: bool,
+}
+
+impl Default for Style {
+    fn default() -> Self {
+        Self { foreground: Color::Reset, background: Color::Reset, reverse: false }
+    }
+}
+
+impl Style {
+    const fn fg(foreground: Color) -> Self {
+        Self { foreground, background: Color::Reset, reverse: false }
+    }
+
+    const REVERSE: Style = Style { foreground: Color::Reset, background: Color::Reset, reverse: true };
+
+    fn convert(&mut self, depth: ColorDepth) {
+        self.foreground = depth.convert(self.foreground);
+        self.background = depth.convert(self.background);
+    }
+}
+
+// `"cyan"` for the text only, or `{ fg = "#282828", bg = "#fabd2f", reverse = false }`
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StyleFile {
+    Color(String),
+    Table {
+        fg: Option<String>,
+        bg: Option<String>,
+        #[serde(default)]
+        reverse: bool,
+    },
+}
+
+impl TryFrom<StyleFile> for Style {
+    type Error = String;
+
+    fn try_from(file: StyleFile) -> Result<Self, Self::Error> {
+        let color = |name: Option<String>| match name {
+            Some(name) => parse_color(&name).ok_or_else(|| format!("unknown color `{}`", name)),
+            None => Ok(Color::Reset),
+        };
+        match file {
+            StyleFile::Color(name) => Ok(Style::fg(color(Some(name))?)),
+            StyleFile::Table { fg, bg, reverse } => Ok(Style { foreground: color(fg)?, background: color(bg)?, reverse }),
+        }
+    }
+}
+
+// 配色, keys a theme file leaves out keep the default
+#[derive(Clone, Copy, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Theme {
+    pub keyword: Style,
+    #[serde(rename = "type")]
+    pub type_name: Style,
+    pub number: Style,
+    pub string: Style,
+    pub char_literal: Style,
+    pub comment: Style,
+    pub lifetime: Style,
+    pub attribute: Style,
+    #[serde(rename = "macro")]
+    pub macro_call: Style,
+    pub search_match: Style,
+    pub selection: Style,
+    pub gutter: Style,
+    pub status_bar: Style,
+    pub message_bar: Style,
+    #[serde(skip)]
+    depth: ColorDepth, // fixed keyword colors of the syntax files are converted when drawn
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self {
+            keyword: Style::fg(Color::DarkRed),
+            type_name: Style::fg(Color::DarkMagenta),
+            number: Style::fg(Color::Cyan),
+            string: Style::fg(Color::Yellow),
+            char_literal: Style::fg(Color::DarkYellow),
+            comment: Style::fg(Color::DarkGrey),
+            lifetime: Style::fg(Color::DarkCyan),
+            attribute: Style::fg(Color::DarkGreen),
+            macro_call: Style::fg(Color::Magenta),
+            search_match: Style::fg(Color::Blue),
+            selection: Style::REVERSE,
+            gutter: Style::fg(Color::DarkGrey),
+            status_bar: Style::REVERSE,
+            message_bar: Style::default(),
+            depth: ColorDepth::TrueColor,
+        }
+    }
+}
+
+impl Theme {
+    // `themes/<name>.toml` of the config directory, then the built-in ones
+    pub fn load(name: &str, depth: ColorDepth) -> Result<Self, String> {
+        let user_file = Config::dir().map(|dir| dir.join("themes").join(format!("{}.toml", name)));
+        let mut theme: Theme = match user_file.filter(|path| path.is_file()) {
+            Some(path) => {
+                let text = fs::read_to_string(&path).map_err(|err| format!("{}: {}", path.display(), err))?;
+                toml::from_str(&text).map_err(|err| format!("{}: {}", path.display(), Config::describe_error(&text, err)))?
+            }
+            None => {
+                let (_, text) = BUILT_IN.iter()
+                    .find(|(it, _)| *it == name)
+                    .ok_or_else(|| format!("unknown theme `{}`, expected one of {}", name, Theme::names().join(", ")))?;
+                toml::from_str(text).map_err(|err| format!("{}.toml: {}", name, Config::describe_error(text, err)))?
+            }
+        };
+        theme.depth = depth;
+        for style in theme.styles() {
+            style.convert(depth);
+        }
+        Ok(theme)
+    }
+
+    // the built-in themes and those of the config directory
+    pub fn names() -> Vec<String> {
+        let mut names: Vec<String> = BUILT_IN.iter().map(|(name, _)| name.to_string()).collect();
+        let files = Config::dir().and_then(|dir| fs::read_dir(dir.join("themes")).ok()).into_iter().flatten();
+        for path in files.filter_map(|entry| Some(entry.ok()?.path())) {
+            match path.file_stem().and_then(|stem| stem.to_str()) {
+                Some(name) if path.extension().is_some_and(|ext| ext == "toml") && !names.iter().any(|it| it == name) => {
+                    names.push(name.to_string());
+                }
+                _ => {}
+            }
+        }
+        names
+    }
+
+    fn styles(&mut self) -> [&mut Style; 14] {
+        [
+            &mut self.keyword,
+            &mut self.type_name,
+            &mut self.number,
+            &mut self.string,
+            &mut self.char_literal,
+            &mut self.comment,
+            &mut self.lifetime,
+            &mut self.attribute,
+            &mut self.macro_call,
+            &mut self.search_match,
+            &mut self.selection,
+            &mut self.gutter,
+            &mut self.status_bar,
+            &mut self.message_bar,
+        ]
+    }
+
+    // keyword groups without a scope keep the color of their syntax definition
+    pub fn style(&self, highlight: &HighlightType) -> Style {
+        match highlight {
+            HighlightType::Normal => Style::default(),
+            HighlightType::Keyword => self.keyword,
+            HighlightType::Type => self.type_name,
+            HighlightType::Number => self.number,
+            HighlightType::SearchMatch => self.search_match,
+            HighlightType::Selection => self.selection,
+            HighlightType::String => self.string,
+            HighlightType::CharLiteral => self.char_literal,
+            HighlightType::Comment | HighlightType::MultilineComment => self.comment,
+            HighlightType::Lifetime => self.lifetime,
+            HighlightType::Attribute => self.attribute,
+            HighlightType::Macro => self.macro_call,
+            HighlightType::Other(color) => Style::fg(self.depth.convert(*color)),
+        }
+    }
+}
+
+// `darkred`, `#ff8800` or a 256-color number like `208`
+pub fn parse_color(name: &str) -> Option<Color> {
+    if let Some(hex) = name.strip_prefix('#') {
+        if hex.len() != 6 || !hex.is_ascii() {
+            return None;
+        }
+        let channel = |at: usize| u8::from_str_radix(&hex[at..at + 2], 16).ok();
+        return Some(Color::Rgb { r: channel(0)?, g: channel(2)?, b: channel(4)? });
+    }
+    if let Ok(value) = name.parse::<u8>() {
+        return Some(Color::AnsiValue(value));
+    }
+    let color = match name.to_ascii_lowercase().replace(['_', '-'], "").as_str() {
+        "reset" | "default" => Color::Reset,
+        "black" => Color::Black,
+        "darkgrey" | "darkgray" => Color::DarkGrey,
+        "red" => Color::Red,
+        "darkred" => Color::DarkRed,
+        "green" => Color::Green,
+        "darkgreen" => Color::DarkGreen,
+        "yellow" => Color::Yellow,
+        "darkyellow" => Color::DarkYellow,
+        "blue" => Color::Blue,
+        "darkblue" => Color::DarkBlue,
+        "magenta" => Color::Magenta,
+        "darkmagenta" => Color::DarkMagenta,
+        "cyan" => Color::Cyan,
+        "darkcyan" => Color::DarkCyan,
+        "white" => Color::White,
+        "grey" | "gray" => Color::Grey,
+        _ => return None,
+    };
+    Some(color)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rgb(r: u8, g: u8, b: u8) -> Color {
+        Color::Rgb { r, g, b }
+    }
+
+    #[test]
+    fn the_256_colors() {
+        assert_eq!(ansi_to_rgb(1), (205, 0, 0));
+        assert_eq!(ansi_to_rgb(16), (0, 0, 0));
+        assert_eq!(ansi_to_rgb(196), (255, 0, 0));
+        assert_eq!(ansi_to_rgb(231), (255, 255, 255));
+        assert_eq!(ansi_to_rgb(232), (8, 8, 8));
+        assert_eq!(ansi_to_rgb(255), (238, 238, 238));
+        // every color past the first 16 comes back as itself
+        for value in 16..=255 {
+            assert_eq!(rgb_to_ansi(ansi_to_rgb(value)), value);
+        }
+    }
+
+    #[test]
+    fn edges_of_the_cube() {
+        assert_eq!(rgb_to_ansi((0, 0, 0)), 16);
+        assert_eq!(rgb_to_ansi((255, 255, 255)), 231);
+        assert_eq!(rgb_to_ansi((255, 0, 0)), 196);
+        assert_eq!(rgb_to_ansi((0, 0, 255)), 21);
+        assert_eq!(rgb_to_ansi((0, 95, 135)), 24);
+        // halfway between the steps 0 and 95 of the cube
+        assert_eq!(rgb_to_ansi((255, 47, 0)), 196);
+        assert_eq!(rgb_to_ansi((255, 48, 0)), 202);
+        assert_eq!(rgb_to_ansi((250, 0, 5)), 196);
+    }
+
+    #[test]
+    fn the_grayscale_ramp() {
+        assert_eq!(rgb_to_ansi((8, 8, 8)), 232);
+        assert_eq!(rgb_to_ansi((128, 128, 128)), 244);
+        assert_eq!(rgb_to_ansi((238, 238, 238)), 255);
+        assert_eq!(rgb_to_ansi((130, 128, 126)), 244);
+        // past the ends of the ramp the cube is nearer
+        assert_eq!(rgb_to_ansi((2, 2, 2)), 16);
+        assert_eq!(rgb_to_ansi((250, 250, 250)), 231);
+    }
+
+    #[test]
+    fn down_to_16_colors() {
+        let depth = ColorDepth::Ansi16;
+        assert_eq!(depth.convert(rgb(255, 0, 0)), Color::Red);
+        assert_eq!(depth.convert(rgb(200, 10, 0)), Color::DarkRed);
+        assert_eq!(depth.convert(rgb(0, 0, 0)), Color::Black);
+        assert_eq!(depth.convert(rgb(250, 250, 250)), Color::White);
+        assert_eq!(depth.convert(rgb(128, 128, 128)), Color::DarkGrey);
+        assert_eq!(depth.convert(Color::AnsiValue(196)), Color::Red);
+        assert_eq!(depth.convert(Color::AnsiValue(244)), Color::DarkGrey);
+        assert_eq!(depth.convert(Color::AnsiValue(3)), Color::DarkYellow);
+        // the named colors work everywhere
+        assert_eq!(depth.convert(Color::DarkMagenta), Color::DarkMagenta);
+        assert_eq!(depth.convert(Color::Reset), Color::Reset);
+    }
+
+    #[test]
+    fn only_what_the_terminal_lacks_is_converted() {
+        assert_eq!(ColorDepth::TrueColor.convert(rgb(1, 2, 3)), rgb(1, 2, 3));
+        assert_eq!(ColorDepth::TrueColor.convert(Color::AnsiValue(100)), Color::AnsiValue(100));
+        assert_eq!(ColorDepth::Ansi256.convert(rgb(255, 0, 0)), Color::AnsiValue(196));
+        assert_eq!(ColorDepth::Ansi256.convert(Color::AnsiValue(100)), Color::AnsiValue(100));
+        assert_eq!(ColorDepth::Ansi256.convert(Color::Cyan), Color::Cyan);
+    }
+
+    #[test]
+    fn a_loaded_theme_is_converted() {
+        let theme = Theme::load("gruvbox", ColorDepth::Ansi16).unwrap();
+        let named = |color: Color| !matches!(color, Color::Rgb { .. } | Color::AnsiValue(_));
+        assert!(named(theme.keyword.foreground) && named(theme.search_match.background));
+        assert_eq!(theme.style(&HighlightType::Other(rgb(255, 0, 0))).foreground, Color::Red);
+    }
+}
@@ -0,0 +1,916 @@
+use std::{ cmp, mem };
+use crossterm::event::{ KeyCode, KeyEvent, KeyModifiers };
+use crate::editor_row::EditorRows;
+use crate::history::Position;
+use crate::Output;
+use unicode_segmentation::UnicodeSegmentation;
+
+// 模式
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    Normal,
+    Insert,
+    Visual,
+}
+
+impl Mode {
+    pub fn indicator(&self) -> &str {
+        match self {
+            Mode::Normal => "-- NORMAL --",
+            Mode::Insert => "-- INSERT --",
+            Mode::Visual => "-- VISUAL --",
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Operator {
+    Delete,
+    Change,
+    Yank,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Motion {
+    Left,
+    Right,
+    Up,
+    Down,
+    WordForward,
+    WordBackward,
+    WordEnd,
+    LineStart,
+    LineEnd,
+    FirstLine,
+    LastLine,
+    PageUp,
+    PageDown,
+    CurrentLine, // dd, cc, yy
+}
+
+impl Motion {
+    fn is_linewise(self) -> bool {
+        matches!(
+            self,
+            Motion::Up |
+                Motion::Down |
+                Motion::FirstLine |
+                Motion::LastLine |
+                Motion::PageUp |
+                Motion::PageDown |
+                Motion::CurrentLine
+        )
+    }
+    fn is_inclusive(self) -> bool {
+        matches!(self, Motion::WordEnd | Motion::LineEnd)
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum InsertAt {
+    Cursor,
+    After,
+    LineStart,
+    LineEnd,
+    LineBelow,
+    LineAbove,
+}
+
+#[derive(Clone, Copy)]
+pub enum Command {
+    Move(Motion),
+    Operate(Operator, Motion),
+    VisualOperate(Operator),
+    Insert(InsertAt),
+    DeleteChar,
+    Paste {
+        before: bool,
+    },
+    Visual,
+    Undo,
+    Redo,
+    Repeat,
+}
+
+impl Command {
+    // commands repeated by `.`
+    fn is_change(&self) -> bool {
+        match self {
+            Command::Operate(operator, _) => *operator != Operator::Yank,
+            Command::Insert(_) | Command::DeleteChar | Command::Paste { .. } => true,
+            _ => false,
+        }
+    }
+}
+
+pub enum Parsed {
+    Pending,
+    Command(Command, Option<usize>),
+    Unhandled,
+}
+
+pub struct Vim {
+    count: Option<usize>,
+    operator: Option<(Operator, Option<usize>)>,
+    pending_g: bool,
+    keys: Vec<KeyEvent>, // keys of the command being typed
+    recording: Option<Vec<KeyEvent>>, // change that continues in insert mode
+    last_change: Vec<KeyEvent>,
+}
+
+impl Vim {
+    pub fn new() -> Self {
+        Self {
+            count: None,
+            operator: None,
+            pending_g: false,
+            keys: Vec::new(),
+            recording: None,
+            last_change: Vec::new(),
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.count = None;
+        self.operator = None;
+        self.pending_g = false;
+        self.keys.clear();
+    }
+
+    pub fn feed(&mut self, key: KeyEvent, mode: Mode) -> Parsed {
+        self.keys.push(key);
+        let parsed = self.parse(key, mode);
+        if matches!(parsed, Parsed::Unhandled) {
+            self.reset();
+        }
+        parsed
+    }
+
+    fn parse(&mut self, key: KeyEvent, mode: Mode) -> Parsed {
+        if key.modifiers == KeyModifiers::CONTROL && key.code == KeyCode::Char('r') {
+            return self.finish(Command::Redo);
+        }
+        if key.modifiers != KeyModifiers::NONE && key.modifiers != KeyModifiers::SHIFT {
+            return Parsed::Unhandled;
+        }
+        if let KeyCode::Char(ch @ '0'..='9') = key.code {
+            if ch != '0' || self.count.is_some() {
+                let digit = (ch as usize) - ('0' as usize);
+                self.count = Some(self.count.unwrap_or(0).saturating_mul(10).saturating_add(digit));
+                return Parsed::Pending;
+            }
+        }
+        if self.pending_g {
+            return match key.code {
+                KeyCode::Char('g') => self.finish_motion(Motion::FirstLine),
+                _ => self.cancel(),
+            };
+        }
+        if key.code == KeyCode::Char('g') {
+            self.pending_g = true;
+            return Parsed::Pending;
+        }
+        let operator = match key.code {
+            KeyCode::Char('d') => Some(Operator::Delete),
+            KeyCode::Char('c') => Some(Operator::Change),
+            KeyCode::Char('y') => Some(Operator::Yank),
+            _ => None,
+        };
+        if let Some((pending, _)) = self.operator {
+            return match (operator, Self::motion(key.code)) {
+                (Some(operator), _) if operator == pending => self.finish_motion(Motion::CurrentLine),
+                (_, Some(motion)) => self.finish_motion(motion),
+                _ => self.cancel(),
+            };
+        }
+        if let Some(operator) = operator {
+            if mode == Mode::Visual {
+                return self.finish(Command::VisualOperate(operator));
+            }
+            self.operator = Some((operator, self.count.take()));
+            return Parsed::Pending;
+        }
+        let command = match key.code {
+            KeyCode::Char('x') | KeyCode::Delete if mode == Mode::Visual => {
+                Command::VisualOperate(Operator::Delete)
+            }
+            KeyCode::Char('v') => Command::Visual,
+            _ if mode == Mode::Visual => {
+                return match Self::motion(key.code) {
+                    Some(motion) => self.finish_motion(motion),
+                    None => self.cancel(),
+                };
+            }
+            KeyCode::Char('x') | KeyCode::Delete => Command::DeleteChar,
+            KeyCode::Char('D') => Command::Operate(Operator::Delete, Motion::LineEnd),
+            KeyCode::Char('C') => Command::Operate(Operator::Change, Motion::LineEnd),
+            KeyCode::Char('i') => Command::Insert(InsertAt::Cursor),
+            KeyCode::Char('a') => Command::Insert(InsertAt::After),
+            KeyCode::Char('I') => Command::Insert(InsertAt::LineStart),
+            KeyCode::Char('A') => Command::Insert(InsertAt::LineEnd),
+            KeyCode::Char('o') => Command::Insert(InsertAt::LineBelow),
+            KeyCode::Char('O') => Command::Insert(InsertAt::LineAbove),
+            KeyCode::Char('p') => Command::Paste { before: false },
+            KeyCode::Char('P') => Command::Paste { before: true },
+            KeyCode::Char('u') => Command::Undo,
+            KeyCode::Char('.') => Command::Repeat,
+            code => {
+                return match Self::motion(code) {
+                    Some(motion) => self.finish_motion(motion),
+                    None => self.cancel(),
+                };
+            }
+        };
+        self.finish(command)
+    }
+
+    fn motion(code: KeyCode) -> Option<Motion> {
+        let motion = match code {
+            KeyCode::Char('h') | KeyCode::Left | KeyCode::Backspace => Motion::Left,
+            KeyCode::Char('l') | KeyCode::Char(' ') | KeyCode::Right => Motion::Right,
+            KeyCode::Char('k') | KeyCode::Up => Motion::Up,
+            KeyCode::Char('j') | KeyCode::Down | KeyCode::Enter => Motion::Down,
+            KeyCode::Char('w') => Motion::WordForward,
+            KeyCode::Char('b') => Motion::WordBackward,
+            KeyCode::Char('e') => Motion::WordEnd,
+            KeyCode::Char('0') | KeyCode::Home => Motion::LineStart,
+            KeyCode::Char('$') | KeyCode::End => Motion::LineEnd,
+            KeyCode::Char('G') => Motion::LastLine,
+            KeyCode::PageUp => Motion::PageUp,
+            KeyCode::PageDown => Motion::PageDown,
+            _ => {
+                return None;
+            }
+        };
+        Some(motion)
+    }
+
+    fn finish_motion(&mut self, motion: Motion) -> Parsed {
+        match self.operator {
+            Some((operator, _)) => self.finish(Command::Operate(operator, motion)),
+            None => self.finish(Command::Move(motion)),
+        }
+    }
+
+    fn finish(&mut self, command: Command) -> Parsed {
+        // 2d3w deletes 6 words
+        let count = match (self.operator.and_then(|(_, count)| count), self.count) {
+            (Some(a), Some(b)) => Some(a.saturating_mul(b)),
+            (a, b) => a.or(b),
+        };
+        self.count = None;
+        self.operator = None;
+        self.pending_g = false;
+        Parsed::Command(command, count)
+    }
+
+    // unknown keys are swallowed, never inserted
+    fn cancel(&mut self) -> Parsed {
+        self.reset();
+        Parsed::Pending
+    }
+
+    pub fn take_keys(&mut self) -> Vec<KeyEvent> {
+        mem::take(&mut self.keys)
+    }
+
+    pub fn record(&mut self, key: KeyEvent) {
+        if let Some(keys) = self.recording.as_mut() {
+            keys.push(key);
+        }
+    }
+
+    pub fn stop_recording(&mut self) {
+        if let Some(keys) = self.recording.take() {
+            self.last_change = keys;
+        }
+    }
+
+    pub fn last_change(&self) -> Vec<KeyEvent> {
+        self.last_change.clone()
+    }
+
+    pub fn execute(
+        &mut self,
+        command: Command,
+        count: Option<usize>,
+        keys: Vec<KeyEvent>,
+        output: &mut Output
+    ) {
+        let cursor = output.cursor_position();
+        let times = count.unwrap_or(1);
+        match command {
+            Command::Move(motion) => {
+                let target = motion_target(output, motion, cursor, count);
+                output.history.break_group();
+                output.set_cursor_position(target);
+            }
+            Command::Operate(operator, motion) => {
+                if output.editor_rows.number_of_rows() == 0 {
+                    return;
+                }
+                // `cw` changes to the end of the word, like `ce`
+                let (motion, target) = if
+                    operator == Operator::Change &&
+                    motion == Motion::WordForward &&
+                    !matches!(char_class(&output.editor_rows, cursor), CharClass::Blank)
+                {
+                    let word_end = current_word_end(&output.editor_rows, cursor);
+                    let target = (1..times).fold(word_end, |pos, _| {
+                        next_word_end(&output.editor_rows, pos)
+                    });
+                    (Motion::WordEnd, target)
+                } else {
+                    (motion, motion_target(output, motion, cursor, count))
+                };
+                let (from, to, linewise) = operator_range(&output.editor_rows, motion, cursor, target);
+                self.operate(operator, from, to, linewise, output);
+            }
+            Command::VisualOperate(operator) => {
+                let selection = output.cursor_controller.selection();
+                output.cursor_controller.anchor = None;
+                output.mode = Mode::Normal;
+                if let Some((from, to)) = selection {
+                    if from.1 >= output.editor_rows.number_of_rows() {
+                        return;
+                    }
+                    // visual selections include the character under the cursor
+                    let (_, to, _) = operator_range(&output.editor_rows, Motion::WordEnd, from, to);
+                    self.operate(operator, from, to, false, output);
+                }
+            }
+            Command::DeleteChar => {
+                let (x, y) = cursor;
+                if y < output.editor_rows.number_of_rows() {
+                    let len = row_len(&output.editor_rows, y);
+                    if x < len {
+                        self.operate(Operator::Delete, cursor, (cmp::min(x + times, len), y), false, output);
+                    }
+                }
+            }
+            Command::Paste { before } => self.paste(before, times, output),
+            Command::Insert(at) => self.insert(at, output),
+            Command::Visual => {
+                if output.mode == Mode::Visual {
+                    output.cursor_controller.anchor = None;
+                    output.mode = Mode::Normal;
+                } else {
+                    output.cursor_controller.anchor = Some(cursor);
+                    output.mode = Mode::Visual;
+                }
+            }
+            Command::Undo => (0..times).for_each(|_| output.undo()),
+            Command::Redo => (0..times).for_each(|_| output.redo()),
+            Command::Repeat => {}
+        }
+        if command.is_change() {
+            if output.mode == Mode::Insert {
+                self.recording = Some(keys);
+            } else {
+                self.last_change = keys;
+            }
+        }
+        clamp_cursor(output);
+    }
+
+    fn operate(
+        &mut self,
+        operator: Operator,
+        from: Position,
+        to: Position,
+        linewise: bool,
+        output: &mut Output
+    ) {
+        let mut text = output.editor_rows.get_text(from, to);
+        if linewise {
+            text.push('\n');
+        }
+        output.clipboard.set(text, linewise);
+        match operator {
+            Operator::Yank => {
+                output.set_cursor_position(from);
+            }
+            Operator::Delete => {
+                let (start, end) = if linewise {
+                    linewise_delete_range(&output.editor_rows, from.1, to.1)
+                } else {
+                    (from, to)
+                };
+                output.history.begin_group();
+                if start != end {
+                    output.delete_text(start, end);
+                }
+                output.history.end_group();
+                if linewise {
+                    output.set_cursor_position((0, from.1));
+                }
+            }
+            Operator::Change => {
+                // closed when insert mode is left
+                output.history.begin_group();
+                if from != to {
+                    output.delete_text(from, to);
+                }
+                output.mode = Mode::Insert;
+            }
+        }
+    }
+
+    fn paste(&mut self, before: bool, times: usize, output: &mut Output) {
+        let text = match output.clipboard.text.as_ref() {
+            Some(text) => text.repeat(times),
+            None => {
+                return;
+            }
+        };
+        let (x, y) = output.cursor_position();
+        let number_of_rows = output.editor_rows.number_of_rows();
+        output.history.begin_group();
+        if output.clipboard.linewise {
+            if before || number_of_rows == 0 {
+                output.insert_text((0, y), &text);
+                output.set_cursor_position((0, y));
+            } else if y + 1 < number_of_rows {
+                output.insert_text((0, y + 1), &text);
+                output.set_cursor_position((0, y + 1));
+            } else {
+                let text = text.strip_suffix('\n').unwrap_or(&text);
+                output.insert_text((row_len(&output.editor_rows, y), y), &format!("\n{}", text));
+                output.set_cursor_position((0, y + 1));
+            }
+        } else {
+            let len = if y < number_of_rows { row_len(&output.editor_rows, y) } else { 0 };
+            let at = if before || len == 0 { (x, y) } else { (cmp::min(x + 1, len), y) };
+            output.insert_text(at, &text);
+            // cursor on the last pasted character
+            let (x, y) = output.cursor_position();
+            output.set_cursor_position((x.saturating_sub(1), y));
+        }
+        output.history.end_group();
+    }
+
+    fn insert(&mut self, at: InsertAt, output: &mut Output) {
+        let (x, y) = output.cursor_position();
+        let number_of_rows = output.editor_rows.number_of_rows();
+        let len = if y < number_of_rows { row_len(&output.editor_rows, y) } else { 0 };
+        // closed when insert mode is left
+        output.history.begin_group();
+        match at {
+            InsertAt::Cursor => {}
+            InsertAt::After => output.set_cursor_position((cmp::min(x + 1, len), y)),
+            InsertAt::LineStart => {
+                let indent = if y < number_of_rows {
+                    let row = output.editor_rows.get_editor_row(y);
+                    row.row_content.graphemes(true).take_while(|g| g.trim().is_empty()).count()
+                } else {
+                    0
+                };
+                output.set_cursor_position((indent, y));
+            }
+            InsertAt::LineEnd => output.set_cursor_position((len, y)),
+            InsertAt::LineBelow if number_of_rows > 0 => {
+                output.insert_text((len, y), "\n");
+            }
+            InsertAt::LineAbove if number_of_rows > 0 => {
+                output.insert_text((0, y), "\n");
+                output.set_cursor_position((0, y));
+            }
+            InsertAt::LineBelow | InsertAt::LineAbove => {}
+        }
+        output.mode = Mode::Insert;
+    }
+
+    pub fn leave_insert(&mut self, output: &mut Output) {
+        self.stop_recording();
+        output.history.end_group();
+        output.mode = Mode::Normal;
+        let (x, y) = output.cursor_position();
+        output.set_cursor_position((x.saturating_sub(1), y));
+        clamp_cursor(output);
+    }
+}
+
+// normal mode keeps the cursor on an existing character
+pub fn clamp_cursor(output: &mut Output) {
+    if output.mode == Mode::Insert {
+        return;
+    }
+    let number_of_rows = output.editor_rows.number_of_rows();
+    if number_of_rows == 0 {
+        output.set_cursor_position((0, 0));
+        return;
+    }
+    let (x, y) = output.cursor_position();
+    let y = cmp::min(y, number_of_rows - 1);
+    let len = row_len(&output.editor_rows, y);
+    let max_x = if output.mode == Mode::Visual { len } else { len.saturating_sub(1) };
+    output.set_cursor_position((cmp::min(x, max_x), y));
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum CharClass {
+    Blank,
+    Word,
+    Punct,
+    EmptyLine,
+}
+
+fn row_len(editor_rows: &EditorRows, y: usize) -> usize {
+    editor_rows.get_editor_row(y).len()
+}
+
+fn char_class(editor_rows: &EditorRows, (x, y): Position) -> CharClass {
+    if y >= editor_rows.number_of_rows() {
+        return CharClass::Blank;
+    }
+    let row = editor_rows.get_editor_row(y);
+    match row.grapheme(x).and_then(|g| g.chars().next()) {
+        None if row.is_empty() => CharClass::EmptyLine,
+        None => CharClass::Blank,
+        Some(ch) if ch.is_whitespace() => CharClass::Blank,
+        Some(ch) if ch.is_alphanumeric() || ch == '_' => CharClass::Word,
+        Some(_) => CharClass::Punct,
+    }
+}
+
+// [start, end) of the run of graphemes in the class of the one at `(x, y)`, e.g. a double-clicked word
+pub fn word_at(editor_rows: &EditorRows, (x, y): Position) -> (Position, Position) {
+    if y >= editor_rows.number_of_rows() || x >= row_len(editor_rows, y) {
+        return ((x, y), (x, y));
+    }
+    let class = char_class(editor_rows, (x, y));
+    let (mut start, mut end) = (x, x + 1);
+    while start > 0 && char_class(editor_rows, (start - 1, y)) == class {
+        start -= 1;
+    }
+    while end < row_len(editor_rows, y) && char_class(editor_rows, (end, y)) == class {
+        end += 1;
+    }
+    ((start, y), (end, y))
+}
+
+fn step_forward(editor_rows: &EditorRows, (x, y): Position) -> Option<Position> {
+    if x < row_len(editor_rows, y) {
+        Some((x + 1, y))
+    } else if y + 1 < editor_rows.number_of_rows() {
+        Some((0, y + 1))
+    } else {
+        None
+    }
+}
+
+fn step_back(editor_rows: &EditorRows, (x, y): Position) -> Option<Position> {
+    if x > 0 {
+        Some((x - 1, y))
+    } else if y > 0 {
+        Some((row_len(editor_rows, y - 1), y - 1))
+    } else {
+        None
+    }
+}
+
+// w
+pub fn next_word_start(editor_rows: &EditorRows, (mut x, mut y): Position) -> Position {
+    let class = char_class(editor_rows, (x, y));
+    match class {
+        CharClass::EmptyLine if y + 1 < editor_rows.number_of_rows() => {
+            x = 0;
+            y += 1;
+        }
+        CharClass::EmptyLine => {
+            return (x, y);
+        }
+        CharClass::Word | CharClass::Punct => {
+            while char_class(editor_rows, (x, y)) == class {
+                x += 1;
+            }
+        }
+        CharClass::Blank => {}
+    }
+    loop {
+        match char_class(editor_rows, (x, y)) {
+            CharClass::Blank => {
+                match step_forward(editor_rows, (x, y)) {
+                    Some(pos) => {
+                        (x, y) = pos;
+                    }
+                    None => {
+                        return (x, y);
+                    }
+                }
+            }
+            _ => {
+                return (x, y);
+            }
+        }
+    }
+}
+
+// b
+pub fn previous_word_start(editor_rows: &EditorRows, pos: Position) -> Position {
+    let mut pos = match step_back(editor_rows, pos) {
+        Some(pos) => pos,
+        None => {
+            return pos;
+        }
+    };
+    while char_class(editor_rows, pos) == CharClass::Blank {
+        pos = match step_back(editor_rows, pos) {
+            Some(pos) => pos,
+            None => {
+                return pos;
+            }
+        };
+    }
+    let class = char_class(editor_rows, pos);
+    while pos.0 > 0 && char_class(editor_rows, (pos.0 - 1, pos.1)) == class {
+        pos.0 -= 1;
+    }
+    pos
+}
+
+// e
+fn next_word_end(editor_rows: &EditorRows, pos: Position) -> Position {
+    let mut pos = match step_forward(editor_rows, pos) {
+        Some(pos) => pos,
+        None => {
+            return pos;
+        }
+    };
+    while matches!(char_class(editor_rows, pos), CharClass::Blank | CharClass::EmptyLine) {
+        pos = match step_forward(editor_rows, pos) {
+            Some(pos) => pos,
+            None => {
+                return pos;
+            }
+        };
+    }
+    current_word_end(editor_rows, pos)
+}
+
+fn current_word_end(editor_rows: &EditorRows, (mut x, y): Position) -> Position {
+    let class = char_class(editor_rows, (x, y));
+    while x + 1 < row_len(editor_rows, y) && char_class(editor_rows, (x + 1, y)) == class {
+        x += 1;
+    }
+    (x, y)
+}
+
+fn motion_target(output: &Output, motion: Motion, (x, y): Position, count: Option<usize>) -> Position {
+    let editor_rows = &output.editor_rows;
+    let number_of_rows = editor_rows.number_of_rows();
+    if number_of_rows == 0 {
+        return (0, 0);
+    }
+    let last_row = number_of_rows - 1;
+    let y = cmp::min(y, last_row);
+    let times = count.unwrap_or(1);
+    let page = output.cursor_controller.screen_rows;
+    let vertical = |y: usize| (cmp::min(x, row_len(editor_rows, y)), y);
+    match motion {
+        Motion::Left => (x.saturating_sub(times), y),
+        Motion::Right => (cmp::min(x + times, row_len(editor_rows, y)), y),
+        Motion::Up => vertical(y.saturating_sub(times)),
+        Motion::Down => vertical(cmp::min(y.saturating_add(times), last_row)),
+        Motion::PageUp => vertical(y.saturating_sub(times.saturating_mul(page))),
+        Motion::PageDown => vertical(cmp::min(y.saturating_add(times.saturating_mul(page)), last_row)),
+        Motion::WordForward => (0..times).fold((x, y), |pos, _| next_word_start(editor_rows, pos)),
+        Motion::WordBackward => (0..times).fold((x, y), |pos, _| previous_word_start(editor_rows, pos)),
+        Motion::WordEnd => (0..times).fold((x, y), |pos, _| next_word_end(editor_rows, pos)),
+        Motion::LineStart => (0, y),
+        Motion::LineEnd => {
+            let y = cmp::min(y.saturating_add(times - 1), last_row);
+            (row_len(editor_rows, y).saturating_sub(1), y)
+        }
+        Motion::FirstLine => (0, count.map_or(0, |count| cmp::min(count.saturating_sub(1), last_row))),
+        Motion::LastLine => (0, count.map_or(last_row, |count| cmp::min(count.saturating_sub(1), last_row))),
+        Motion::CurrentLine => (x, cmp::min(y.saturating_add(times - 1), last_row)),
+    }
+}
+
+// the text an operator works on: (from, to, linewise)
+fn operator_range(
+    editor_rows: &EditorRows,
+    motion: Motion,
+    cursor: Position,
+    target: Position
+) -> (Position, Position, bool) {
+    let (from, mut to) = if (cursor.1, cursor.0) <= (target.1, target.0) {
+        (cursor, target)
+    } else {
+        (target, cursor)
+    };
+    if motion.is_linewise() {
+        return ((0, from.1), (row_len(editor_rows, to.1), to.1), true);
+    }
+    if motion.is_inclusive() {
+        to.0 = cmp::min(to.0 + 1, row_len(editor_rows, to.1));
+    } else if to.1 > from.1 && to.0 == 0 {
+        // `dw` on the last word of a row keeps the line break
+        to = (row_len(editor_rows, to.1 - 1), to.1 - 1);
+    }
+    (from, to, false)
+}
+
+fn linewise_delete_range(editor_rows: &EditorRows, first: usize, last: usize) -> (Position, Position) {
+    if last + 1 < editor_rows.number_of_rows() {
+        ((0, first), (0, last + 1))
+    } else if first > 0 {
+        ((row_len(editor_rows, first - 1), first - 1), (row_len(editor_rows, last), last))
+    } else {
+        ((0, first), (row_len(editor_rows, last), last))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use crossterm::event::{ KeyCode, KeyEvent, KeyModifiers };
+    use crate::{ Editor, Output };
+    use crate::buffer::Buffer;
+    use crate::config::Config;
+    use crate::editor_row::EditorRows;
+    use crate::keymap::Keymap;
+    use crate::status_message::StatusMessage;
+    use crate::syntax_file::Syntaxes;
+    use crate::theme::Theme;
+    use super::Mode;
+
+    const WIN_SIZE: (usize, usize) = (80, 20);
+
+    // a vim profile editor on `rows`, in Normal mode at the start of the first row
+    fn editor(rows: &[&str]) -> Editor {
+        let mut editor_rows = EditorRows::new();
+        for (at, row) in rows.iter().enumerate() {
+            editor_rows.insert_row(at, row.to_string());
+        }
+        let buffers = vec![Buffer::new(editor_rows, WIN_SIZE)];
+        let status_message = StatusMessage::new(String::new());
+        let output = Output::from_buffers(WIN_SIZE, buffers, status_message, Config::default(), Syntaxes::load().0, Theme::default());
+        Editor::with_output(output, Keymap::new("vim", &BTreeMap::new()).0)
+    }
+
+    // `\x1b` is Esc, `\n` is Enter
+    fn keys(editor: &mut Editor, keys: &str) {
+        for ch in keys.chars() {
+            let code = match ch {
+                '\x1b' => KeyCode::Esc,
+                '\n' => KeyCode::Enter,
+                ch => KeyCode::Char(ch),
+            };
+            editor.process_key(KeyEvent::new(code, KeyModifiers::NONE)).unwrap();
+        }
+    }
+
+    fn text(editor: &Editor) -> Vec<String> {
+        let editor_rows = &editor.output.editor_rows;
+        (0..editor_rows.number_of_rows()).map(|at| editor_rows.get_editor_row(at).row_content).collect()
+    }
+
+    fn cursor(editor: &Editor) -> (usize, usize) {
+        editor.output.cursor_position()
+    }
+
+    #[test]
+    fn starts_in_normal_mode() {
+        let mut editor = editor(&["abc"]);
+        assert!(editor.output.mode == Mode::Normal);
+        keys(&mut editor, "ix\x1b");
+        assert_eq!(text(&editor), ["xabc"]);
+        assert!(editor.output.mode == Mode::Normal);
+        assert_eq!(cursor(&editor), (0, 0));
+    }
+
+    #[test]
+    fn motions() {
+        let mut editor = editor(&["foo bar baz", "qux", "", "last line"]);
+        keys(&mut editor, "w");
+        assert_eq!(cursor(&editor), (4, 0));
+        keys(&mut editor, "e");
+        assert_eq!(cursor(&editor), (6, 0));
+        keys(&mut editor, "$");
+        assert_eq!(cursor(&editor), (10, 0));
+        keys(&mut editor, "j");
+        assert_eq!(cursor(&editor), (2, 1));
+        keys(&mut editor, "G");
+        assert_eq!(cursor(&editor).1, 3);
+        keys(&mut editor, "b");
+        assert_eq!(cursor(&editor), (0, 2));
+        keys(&mut editor, "gg0");
+        assert_eq!(cursor(&editor), (0, 0));
+    }
+
+    #[test]
+    fn delete_word_and_repeat() {
+        let mut editor = editor(&["foo bar baz"]);
+        keys(&mut editor, "dw");
+        assert_eq!(text(&editor), ["bar baz"]);
+        keys(&mut editor, ".");
+        assert_eq!(text(&editor), ["baz"]);
+        keys(&mut editor, "u");
+        assert_eq!(text(&editor), ["bar baz"]);
+        keys(&mut editor, "2.");
+        assert_eq!(text(&editor), [""]);
+        keys(&mut editor, "u");
+        assert_eq!(text(&editor), ["bar baz"]);
+    }
+
+    #[test]
+    fn change_word_and_repeat() {
+        let mut editor = editor(&["foo bar baz"]);
+        keys(&mut editor, "cwone\x1b");
+        assert_eq!(text(&editor), ["one bar baz"]);
+        keys(&mut editor, "w.");
+        assert_eq!(text(&editor), ["one one baz"]);
+        keys(&mut editor, "u");
+        assert_eq!(text(&editor), ["one bar baz"]);
+    }
+
+    #[test]
+    fn counts() {
+        let mut editor = editor(&["abcdef", "2", "3", "4"]);
+        keys(&mut editor, "3x");
+        assert_eq!(text(&editor), ["def", "2", "3", "4"]);
+        keys(&mut editor, "j2dd");
+        assert_eq!(text(&editor), ["def", "4"]);
+        assert_eq!(cursor(&editor), (0, 1));
+        keys(&mut editor, "2u");
+        assert_eq!(text(&editor), ["abcdef", "2", "3", "4"]);
+    }
+
+    #[test]
+    fn counts_past_the_end_stop_there() {
+        let mut editor = editor(&["abc", "2", "3"]);
+        keys(&mut editor, "l10x");
+        assert_eq!(text(&editor), ["a", "2", "3"]);
+        assert_eq!(cursor(&editor), (0, 0));
+        keys(&mut editor, "10j");
+        assert_eq!(cursor(&editor), (0, 2));
+        keys(&mut editor, "k5dd");
+        assert_eq!(text(&editor), ["a"]);
+        assert_eq!(cursor(&editor), (0, 0));
+    }
+
+    #[test]
+    fn delete_to_the_last_line() {
+        let mut editor = editor(&["1", "2", "3", "4"]);
+        keys(&mut editor, "jdG");
+        assert_eq!(text(&editor), ["1"]);
+        assert_eq!(cursor(&editor), (0, 0));
+    }
+
+    #[test]
+    fn the_last_line() {
+        let mut editor = editor(&["1", "2", "3"]);
+        keys(&mut editor, "Gdd");
+        assert_eq!(text(&editor), ["1", "2"]);
+        assert_eq!(cursor(&editor), (0, 1));
+        keys(&mut editor, "jyyp");
+        assert_eq!(text(&editor), ["1", "2", "2"]);
+        assert_eq!(cursor(&editor), (0, 2));
+        keys(&mut editor, "ox\x1b");
+        assert_eq!(text(&editor), ["1", "2", "2", "x"]);
+    }
+
+    #[test]
+    fn paste_after_and_before() {
+        let mut editor = editor(&["one", "two"]);
+        keys(&mut editor, "yyjp");
+        assert_eq!(text(&editor), ["one", "two", "one"]);
+        keys(&mut editor, "ggP");
+        assert_eq!(text(&editor), ["one", "one", "two", "one"]);
+        keys(&mut editor, "xp");
+        assert_eq!(text(&editor), ["noe", "one", "two", "one"]);
+        keys(&mut editor, "0xP");
+        assert_eq!(text(&editor), ["noe", "one", "two", "one"]);
+    }
+
+    #[test]
+    fn visual_delete() {
+        let mut editor = editor(&["abc", "def", "ghi"]);
+        keys(&mut editor, "lvjd");
+        assert_eq!(text(&editor), ["af", "ghi"]);
+        assert!(editor.output.mode == Mode::Normal);
+        keys(&mut editor, "vl\x1b");
+        assert!(editor.output.mode == Mode::Normal && editor.output.cursor_controller.anchor.is_none());
+    }
+
+    #[test]
+    fn open_line_and_repeat() {
+        let mut editor = editor(&["a"]);
+        keys(&mut editor, "ob\x1b2.");
+        assert_eq!(text(&editor), ["a", "b", "b", "b"]);
+        keys(&mut editor, "u");
+        assert_eq!(text(&editor), ["a", "b"]);
+    }
+
+    #[test]
+    fn an_empty_buffer() {
+        let mut editor = editor(&[]);
+        keys(&mut editor, "xdddwGp");
+        assert!(text(&editor).iter().all(String::is_empty));
+        assert_eq!(cursor(&editor), (0, 0));
+        keys(&mut editor, "ihi\x1byyp");
+        assert_eq!(text(&editor), ["hi", "hi"]);
+    }
+}
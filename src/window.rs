@@ -0,0 +1,222 @@
+use std::{ cmp, mem };
+
+use crate::cursor_xy::CursorController;
+
+// 窗口: buffer 的一个视图, 多个窗口可以显示同一个 buffer
+pub struct Window {
+    pub buffer: usize,
+    pub cursor_controller: CursorController,
+}
+
+#[derive(Clone, Copy)]
+pub struct Rect {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize, // including the status line
+}
+
+impl Rect {
+    // a vertical split leaves one column between the halves for the separator
+    fn split(self, vertical: bool, ratio: f64) -> (Rect, Rect) {
+        if vertical {
+            let available = self.width.saturating_sub(1);
+            let width = share(available, ratio, 1);
+            (
+                Rect { width, ..self },
+                Rect { x: self.x + width + 1, width: available - width, ..self },
+            )
+        } else {
+            let height = share(self.height, ratio, 2);
+            (
+                Rect { height, ..self },
+                Rect { y: self.y + height, height: self.height - height, ..self },
+            )
+        }
+    }
+}
+
+// `ratio` of `total`, leaving at least `min` to both sides when possible
+fn share(total: usize, ratio: f64, min: usize) -> usize {
+    let min = cmp::min(min, total / 2);
+    cmp::max(min, cmp::min(total - min, ((total as f64) * ratio).round() as usize))
+}
+
+// 窗口布局
+pub enum Layout {
+    Window(usize),
+    // `vertical` puts the windows side by side, `ratio` is the share of `first`
+    Split {
+        vertical: bool,
+        ratio: f64,
+        first: Box<Layout>,
+        second: Box<Layout>,
+    },
+}
+
+impl Layout {
+    pub fn contains(&self, window: usize) -> bool {
+        match self {
+            Layout::Window(idx) => *idx == window,
+            Layout::Split { first, second, .. } => first.contains(window) || second.contains(window),
+        }
+    }
+
+    // every window with its place on the screen, plus the separator columns
+    pub fn rects(&self, rect: Rect, windows: &mut Vec<(usize, Rect)>, separators: &mut Vec<Rect>) {
+        match self {
+            Layout::Window(idx) => windows.push((*idx, rect)),
+            Layout::Split { vertical, ratio, first, second } => {
+                let (first_rect, second_rect) = rect.split(*vertical, *ratio);
+                if *vertical && rect.width > 0 {
+                    separators.push(Rect { x: first_rect.x + first_rect.width, width: 1, ..rect });
+                }
+                first.rects(first_rect, windows, separators);
+                second.rects(second_rect, windows, separators);
+            }
+        }
+    }
+
+    // `window` shares its place with `new_window`
+    pub fn split(&mut self, window: usize, new_window: usize, vertical: bool) {
+        match self {
+            Layout::Window(idx) if *idx == window => {
+                *self = Layout::Split {
+                    vertical,
+                    ratio: 0.5,
+                    first: Box::new(Layout::Window(window)),
+                    second: Box::new(Layout::Window(new_window)),
+                };
+            }
+            Layout::Window(_) => {}
+            Layout::Split { first, second, .. } => {
+                first.split(window, new_window, vertical);
+                second.split(window, new_window, vertical);
+            }
+        }
+    }
+
+    // the sibling of `window` takes its place, the windows after it move down by one
+    pub fn remove(&mut self, window: usize) {
+        match self {
+            Layout::Window(idx) => {
+                if *idx > window {
+                    *idx -= 1;
+                }
+            }
+            Layout::Split { first, second, .. } => {
+                let sibling = match (&**first, &**second) {
+                    (Layout::Window(idx), _) if *idx == window => Some(mem::replace(&mut **second, Layout::Window(0))),
+                    (_, Layout::Window(idx)) if *idx == window => Some(mem::replace(&mut **first, Layout::Window(0))),
+                    _ => None,
+                };
+                match sibling {
+                    Some(sibling) => {
+                        *self = sibling;
+                        self.remove(window);
+                    }
+                    None => {
+                        first.remove(window);
+                        second.remove(window);
+                    }
+                }
+            }
+        }
+    }
+
+    // grows `window` by `delta` rows / columns inside the nearest split of that direction
+    pub fn resize(&mut self, window: usize, vertical: bool, delta: isize, rect: Rect) -> bool {
+        if let Layout::Split { vertical: split_vertical, ratio, first, second } = self {
+            let (first_rect, second_rect) = rect.split(*split_vertical, *ratio);
+            let in_first = first.contains(window);
+            let resized = if in_first {
+                first.resize(window, vertical, delta, first_rect)
+            } else if second.contains(window) {
+                second.resize(window, vertical, delta, second_rect)
+            } else {
+                return false;
+            };
+            if resized || *split_vertical != vertical {
+                return resized;
+            }
+            let (total, current) = if vertical {
+                (rect.width.saturating_sub(1), first_rect.width)
+            } else {
+                (rect.height, first_rect.height)
+            };
+            if total == 0 {
+                return false;
+            }
+            let target = (current as isize) + (if in_first { delta } else { -delta });
+            *ratio = ((target as f64) / (total as f64)).clamp(0.0, 1.0);
+            return true;
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // `(0 | 1)` side by side, `(0 / 1)` one above the other
+    fn describe(layout: &Layout) -> String {
+        match layout {
+            Layout::Window(idx) => idx.to_string(),
+            Layout::Split { vertical, first, second, .. } => {
+                format!("({} {} {})", describe(first), if *vertical { "|" } else { "/" }, describe(second))
+            }
+        }
+    }
+
+    // window 0 split into 0 | 1, then 1 into 1 / 2
+    fn three_windows() -> Layout {
+        let mut layout = Layout::Window(0);
+        layout.split(0, 1, true);
+        layout.split(1, 2, false);
+        layout
+    }
+
+    #[test]
+    fn the_sibling_takes_the_place() {
+        let mut layout = three_windows();
+        assert_eq!(describe(&layout), "(0 | (1 / 2))");
+        layout.remove(2);
+        assert_eq!(describe(&layout), "(0 | 1)");
+        layout.remove(0);
+        assert_eq!(describe(&layout), "0");
+    }
+
+    #[test]
+    fn later_windows_move_down() {
+        let mut layout = three_windows();
+        layout.remove(0);
+        assert_eq!(describe(&layout), "(0 / 1)");
+        let mut layout = three_windows();
+        layout.remove(1);
+        assert_eq!(describe(&layout), "(0 | 1)");
+    }
+
+    #[test]
+    fn a_sibling_split_keeps_its_ratio() {
+        let mut layout = three_windows();
+        if let Layout::Split { second, .. } = &mut layout {
+            if let Layout::Split { ratio, .. } = &mut **second {
+                *ratio = 0.25;
+            }
+        }
+        layout.remove(0);
+        assert!(matches!(layout, Layout::Split { vertical: false, ratio, .. } if ratio == 0.25));
+        assert!(layout.contains(1) && !layout.contains(2));
+    }
+
+    #[test]
+    fn removing_an_unknown_window_only_renumbers() {
+        let mut layout = Layout::Window(3);
+        layout.remove(1);
+        assert_eq!(describe(&layout), "2");
+        let mut layout = three_windows();
+        layout.remove(5);
+        assert_eq!(describe(&layout), "(0 | (1 / 2))");
+    }
+}